@@ -0,0 +1,198 @@
+use std::fs;
+use std::io;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+use crate::modules::filesystem::is_excluded;
+
+/// Options of `files.compress`, shared by zip and tar.
+#[derive(Debug, Clone)]
+pub struct CompressOptions {
+    /// Compression level. The range depends on the format, E.g. 0-9 for zip and gzip and 1-22 for zstd
+    pub level: Option<i32>,
+    /// Inputs are relative to this directory, and stored with their path relative to it
+    pub base_dir: Option<PathBuf>,
+    /// Prefix removed from the input paths to get the names stored in the archive
+    pub strip_prefix: Option<PathBuf>,
+    pub exclude: Vec<glob::Pattern>,
+    /// Stores the files the symbolic links point to, instead of the links
+    pub follow_symlinks: bool,
+    /// Stores the files without compression (zip) or with the fastest level
+    pub store_only: bool,
+    /// Number of zstd compression threads, 0 to compress in the calling thread
+    pub workers: u32,
+    /// Enables zstd long-distance matching, finding repetitions up to 128 MB apart
+    pub long_distance: bool,
+    /// A zstd dictionary, E.g. trained with `zstd --train`. Also needed to decompress
+    pub dictionary: Option<Vec<u8>>,
+}
+
+impl Default for CompressOptions {
+    fn default() -> Self {
+        Self {
+            level: None,
+            base_dir: None,
+            strip_prefix: None,
+            exclude: vec![],
+            follow_symlinks: true,
+            store_only: false,
+            workers: 0,
+            long_distance: false,
+            dictionary: None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum EntryKind {
+    File,
+    Dir,
+    Symlink,
+}
+
+/// A file to be added to an archive.
+#[derive(Debug)]
+pub struct SourceEntry {
+    pub path: PathBuf,
+    /// Name in the archive, with `/` as separator
+    pub name: String,
+    pub kind: EntryKind,
+}
+
+/// An entry of an existing archive, as listed by `files.list`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArchiveEntry {
+    pub name: String,
+    pub size: u64,
+    /// Size in the archive. Unknown for the entries of compressed tar archives
+    pub compressed_size: Option<u64>,
+    pub mode: Option<u32>,
+    /// Modification time, in seconds since the Unix epoch
+    pub mtime: Option<i64>,
+    pub is_dir: bool,
+}
+
+/// Lists the files to add to an archive, with their names in the archive.
+///
+/// Without `base_dir` or `strip_prefix`, each input is stored with its file name at the root of the archive, so
+/// `files.compress("a.zip", "/var/log/app")` stores `app/...`.
+pub fn collect_entries(src_files: &[PathBuf], options: &CompressOptions) -> io::Result<Vec<SourceEntry>> {
+    let mut entries = vec![];
+    for src in src_files {
+        let path = match options.base_dir {
+            Some(ref base_dir) => base_dir.join(src),
+            None => src.clone(),
+        };
+        // Fails with the path of missing inputs
+        fs::symlink_metadata(&path).map_err(|e| path_error(&path, e))?;
+        let root_name = archive_name(src, &path, options)?;
+
+        let walker = WalkDir::new(&path)
+            .follow_links(options.follow_symlinks)
+            .sort_by_file_name()
+            .into_iter()
+            .filter_entry(|e| e.depth() == 0 || !is_excluded(&path, e.path(), &options.exclude));
+
+        for entry in walker {
+            let entry = entry?;
+            let rel_path = entry.path().strip_prefix(&path).unwrap();
+            let name = rel_path.components()
+                .fold(root_name.clone(), |name, c| format!("{}/{}", name, c.as_os_str().to_string_lossy()));
+
+            let file_type = entry.file_type();
+            let kind = if file_type.is_symlink() {
+                EntryKind::Symlink
+            } else if file_type.is_dir() {
+                EntryKind::Dir
+            } else {
+                EntryKind::File
+            };
+            entries.push(SourceEntry { path: entry.path().to_path_buf(), name, kind });
+        }
+    }
+    Ok(entries)
+}
+
+/// Adds the path to the message of an I/O error.
+pub(crate) fn path_error(path: &Path, e: io::Error) -> io::Error {
+    io::Error::new(e.kind(), format!("{}: {}", path.display(), e))
+}
+
+/// Returns the name in the archive of an input.
+fn archive_name(src: &Path, path: &Path, options: &CompressOptions) -> io::Result<String> {
+    let name = if options.base_dir.is_some() {
+        Some(src)
+    } else if let Some(ref prefix) = options.strip_prefix {
+        path.strip_prefix(prefix).ok()
+    } else {
+        path.file_name().map(Path::new)
+    };
+
+    let name = name.map(|n| n.components()
+        .filter(|c| matches!(c, std::path::Component::Normal(_)))
+        .map(|c| c.as_os_str().to_string_lossy().to_string())
+        .collect::<Vec<String>>()
+        .join("/"));
+
+    match name {
+        Some(name) if !name.is_empty() && !src.components().any(|c| c == std::path::Component::ParentDir) => Ok(name),
+        _ => Err(io::Error::new(ErrorKind::InvalidInput, format!("Cannot store {} in the archive, use a path \
+            without .. relative to base_dir or strip_prefix", src.display()))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn names(entries: &[SourceEntry]) -> Vec<&str> {
+        entries.iter().map(|e| e.name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_collect_entries() {
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path();
+        fs::create_dir_all(root.join("app/logs")).unwrap();
+        fs::write(root.join("app/main.lua"), "main").unwrap();
+        fs::write(root.join("app/logs/app.log"), "log").unwrap();
+
+        let entries = collect_entries(&[root.join("app")], &CompressOptions::default()).unwrap();
+        assert_eq!(names(&entries), vec!["app", "app/logs", "app/logs/app.log", "app/main.lua"]);
+        assert_eq!(entries[1].kind, EntryKind::Dir);
+
+        let options = CompressOptions { exclude: vec![glob::Pattern::new("*.log").unwrap()], ..Default::default() };
+        let entries = collect_entries(&[root.join("app")], &options).unwrap();
+        assert_eq!(names(&entries), vec!["app", "app/logs", "app/main.lua"]);
+
+        let options = CompressOptions { base_dir: Some(root.to_path_buf()), ..Default::default() };
+        let entries = collect_entries(&[PathBuf::from("app/logs")], &options).unwrap();
+        assert_eq!(names(&entries), vec!["app/logs", "app/logs/app.log"]);
+        assert!(collect_entries(&[PathBuf::from("../app")], &options).is_err());
+
+        let options = CompressOptions { strip_prefix: Some(root.join("app")), ..Default::default() };
+        let entries = collect_entries(&[root.join("app/logs/app.log")], &options).unwrap();
+        assert_eq!(names(&entries), vec!["logs/app.log"]);
+        assert!(collect_entries(&[root.join("app")], &options).is_err());
+
+        assert!(collect_entries(&[root.join("missing")], &CompressOptions::default()).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_collect_symlinks() {
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path();
+        fs::create_dir_all(root.join("app")).unwrap();
+        fs::write(root.join("target.txt"), "target").unwrap();
+        std::os::unix::fs::symlink(root.join("target.txt"), root.join("app/link")).unwrap();
+
+        let entries = collect_entries(&[root.join("app")], &CompressOptions::default()).unwrap();
+        assert_eq!(entries[1].kind, EntryKind::File);
+
+        let options = CompressOptions { follow_symlinks: false, ..Default::default() };
+        let entries = collect_entries(&[root.join("app")], &options).unwrap();
+        assert_eq!(entries[1].kind, EntryKind::Symlink);
+    }
+}
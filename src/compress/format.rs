@@ -0,0 +1,360 @@
+use std::fs::File;
+use std::io;
+use std::io::{Read, Write};
+use std::path::Path;
+use crate::compress::entries::CompressOptions;
+use crate::compress::safe_extract::{ExtractLimits, ExtractOptions};
+
+/// Compression of a tar archive or of a single file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Codec {
+    None,
+    Gzip,
+    Zstd,
+    Xz,
+    Bzip2,
+}
+
+/// The archive formats supported by `files.compress` and `files.decompress`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArchiveFormat {
+    Zip,
+    Tar(Codec),
+    /// A single compressed file, E.g. `.gz`
+    Single(Codec),
+}
+
+/// Largest zstd window accepted when decompressing, as `zstd --long=31`.
+const ZSTD_WINDOW_LOG_MAX: u32 = 31;
+
+pub const SUPPORTED_EXTENSIONS: &str =
+    ".zip, .tar, .tar.gz, .tgz, .tar.zst, .tar.xz, .tar.bz2, .gz, .zst, .xz and .bz2";
+
+const TAR_EXTENSIONS: [(&str, Codec); 10] = [
+    (".tar", Codec::None),
+    (".tar.gz", Codec::Gzip),
+    (".tgz", Codec::Gzip),
+    (".tar.zst", Codec::Zstd),
+    (".tzst", Codec::Zstd),
+    (".tar.xz", Codec::Xz),
+    (".txz", Codec::Xz),
+    (".tar.bz2", Codec::Bzip2),
+    (".tbz2", Codec::Bzip2),
+    (".tbz", Codec::Bzip2),
+];
+
+const SINGLE_EXTENSIONS: [(&str, Codec); 4] = [
+    (".gz", Codec::Gzip),
+    (".zst", Codec::Zstd),
+    (".xz", Codec::Xz),
+    (".bz2", Codec::Bzip2),
+];
+
+impl ArchiveFormat {
+    /// Returns the format matching the extension of a file name.
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = name.to_lowercase();
+        if name.ends_with(".zip") {
+            return Some(ArchiveFormat::Zip);
+        }
+        if let Some((_, codec)) = TAR_EXTENSIONS.iter().find(|(ext, _)| name.ends_with(ext)) {
+            return Some(ArchiveFormat::Tar(*codec));
+        }
+        SINGLE_EXTENSIONS.iter()
+            .find(|(ext, _)| name.ends_with(ext))
+            .map(|(_, codec)| ArchiveFormat::Single(*codec))
+    }
+
+    /// Detects the format of a file by its magic bytes. Compressed files are tar archives if the decompressed
+    /// content has a tar header.
+    pub fn detect(path: &Path) -> io::Result<Option<Self>> {
+        let mut header = vec![];
+        File::open(path)?.take(512).read_to_end(&mut header)?;
+
+        if header.starts_with(b"PK\x03\x04") || header.starts_with(b"PK\x05\x06") {
+            return Ok(Some(ArchiveFormat::Zip));
+        }
+        if is_tar_header(&header) {
+            return Ok(Some(ArchiveFormat::Tar(Codec::None)));
+        }
+
+        let codec = match Codec::from_magic(&header) {
+            Some(codec) => codec,
+            None => return Ok(None),
+        };
+
+        // Content that can't be decoded, E.g. compressed with a zstd dictionary, is left to the extension
+        let mut content = vec![];
+        if codec.decoder(File::open(path)?, None)?.take(512).read_to_end(&mut content).is_err() {
+            return Ok(None);
+        }
+        if is_tar_header(&content) {
+            Ok(Some(ArchiveFormat::Tar(codec)))
+        } else {
+            Ok(Some(ArchiveFormat::Single(codec)))
+        }
+    }
+}
+
+fn is_tar_header(header: &[u8]) -> bool {
+    header.len() >= 262 && &header[257..262] == b"ustar"
+}
+
+impl Codec {
+    fn from_magic(header: &[u8]) -> Option<Self> {
+        if header.starts_with(&[0x1f, 0x8b]) {
+            Some(Codec::Gzip)
+        } else if header.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Some(Codec::Zstd)
+        } else if header.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+            Some(Codec::Xz)
+        } else if header.starts_with(b"BZh") {
+            Some(Codec::Bzip2)
+        } else {
+            None
+        }
+    }
+
+    /// The extension of a single file compressed with this codec, E.g. `.gz`.
+    pub fn extension(&self) -> &'static str {
+        SINGLE_EXTENSIONS.iter()
+            .find(|(_, codec)| codec == self)
+            .map(|(ext, _)| *ext)
+            .unwrap_or("")
+    }
+
+    /// Returns a decoder. The dictionary is only used by zstd, and must be the one used to compress.
+    pub fn decoder<'a, R: Read + 'a>(&self, reader: R, dictionary: Option<&[u8]>) -> io::Result<Box<dyn Read + 'a>> {
+        let decoder: Box<dyn Read> = match self {
+            Codec::None => Box::new(reader),
+            Codec::Gzip => Box::new(flate2::read::MultiGzDecoder::new(reader)),
+            Codec::Zstd => {
+                let mut decoder = zstd::stream::Decoder::with_dictionary(io::BufReader::new(reader), dictionary.unwrap_or(&[]))?;
+                // Archives compressed with long-distance matching can use windows larger than the default limit
+                decoder.window_log_max(ZSTD_WINDOW_LOG_MAX)?;
+                Box::new(decoder)
+            }
+            Codec::Xz => Box::new(liblzma::read::XzDecoder::new_multi_decoder(reader)),
+            Codec::Bzip2 => Box::new(bzip2::read::MultiBzDecoder::new(reader)),
+        };
+        Ok(decoder)
+    }
+
+    /// Returns an encoder with the level of the options, or the default level of the codec. Levels out of the
+    /// range of the codec are clamped. Workers, long-distance matching and the dictionary are only used by zstd.
+    pub fn encoder<W: Write>(&self, writer: W, options: &CompressOptions) -> io::Result<Encoder<W>> {
+        let level = if options.store_only { Some(self.fastest_level()) } else { options.level };
+        let encoder = match self {
+            Codec::None => Encoder::None(writer),
+            Codec::Gzip => {
+                let compression = level.map(|l| flate2::Compression::new(l.clamp(0, 9) as u32)).unwrap_or_default();
+                Encoder::Gzip(flate2::write::GzEncoder::new(writer, compression))
+            }
+            // 0 = default compression level
+            Codec::Zstd => {
+                let range = zstd::compression_level_range();
+                let level = level.map(|l| l.clamp(*range.start(), *range.end())).unwrap_or(0);
+                let mut encoder = match options.dictionary {
+                    Some(ref dictionary) => zstd::Encoder::with_dictionary(writer, level, dictionary)?,
+                    None => zstd::Encoder::new(writer, level)?,
+                };
+                if options.workers > 0 {
+                    encoder.multithread(options.workers)?;
+                }
+                if options.long_distance {
+                    // Also raises the window to 128 MB
+                    encoder.long_distance_matching(true)?;
+                }
+                Encoder::Zstd(encoder)
+            }
+            Codec::Xz => Encoder::Xz(liblzma::write::XzEncoder::new(writer, level.map(|l| l.clamp(0, 9) as u32).unwrap_or(6))),
+            Codec::Bzip2 => {
+                let compression = level.map(|l| bzip2::Compression::new(l.clamp(1, 9) as u32)).unwrap_or_default();
+                Encoder::Bzip2(bzip2::write::BzEncoder::new(writer, compression))
+            }
+        };
+        Ok(encoder)
+    }
+
+    /// The level compressing the fastest, used to store files almost as they are.
+    pub fn fastest_level(&self) -> i32 {
+        match self {
+            Codec::Zstd | Codec::Bzip2 => 1,
+            _ => 0,
+        }
+    }
+}
+
+/// A writer compressing with one of the codecs. `finish` must be called to write the end of the stream.
+pub enum Encoder<W: Write> {
+    None(W),
+    Gzip(flate2::write::GzEncoder<W>),
+    Zstd(zstd::Encoder<'static, W>),
+    Xz(liblzma::write::XzEncoder<W>),
+    Bzip2(bzip2::write::BzEncoder<W>),
+}
+
+impl<W: Write> Encoder<W> {
+    pub fn finish(self) -> io::Result<W> {
+        match self {
+            Encoder::None(w) => Ok(w),
+            Encoder::Gzip(e) => e.finish(),
+            Encoder::Zstd(e) => e.finish(),
+            Encoder::Xz(e) => e.finish(),
+            Encoder::Bzip2(e) => e.finish(),
+        }
+    }
+
+    fn inner(&mut self) -> &mut dyn Write {
+        match self {
+            Encoder::None(w) => w,
+            Encoder::Gzip(e) => e,
+            Encoder::Zstd(e) => e,
+            Encoder::Xz(e) => e,
+            Encoder::Bzip2(e) => e,
+        }
+    }
+}
+
+impl<W: Write> Write for Encoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner().flush()
+    }
+}
+
+/// Compresses a single file.
+pub fn compress_file(src: &Path, dest: &Path, codec: Codec, options: &CompressOptions) -> io::Result<()> {
+    let mut input = File::open(src)?;
+    let mut encoder = codec.encoder(File::create(dest)?, options)?;
+    io::copy(&mut input, &mut encoder)?;
+    encoder.finish()?.sync_all()
+}
+
+/// Decompresses a single file into `output_dir`. The name of the file is the name of the compressed file without
+/// the codec extension.
+pub fn decompress_file(src: &Path, output_dir: &Path, codec: Codec, options: &ExtractOptions) -> io::Result<()> {
+    let name = src.file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Invalid file name"))?
+        .to_string_lossy();
+    let ext = codec.extension();
+    let out_name = match name.len() > ext.len() && name.to_lowercase().ends_with(ext) {
+        true => name[..name.len() - ext.len()].to_string(),
+        false => format!("{}.out", name),
+    };
+
+    std::fs::create_dir_all(output_dir)?;
+    let mut decoder = codec.decoder(File::open(src)?, options.dictionary.as_deref())?;
+    let mut output = File::create(output_dir.join(out_name))?;
+    let mut limits = ExtractLimits::new(options);
+    let copied = match limits.remaining_size() {
+        Some(remaining) => io::copy(&mut (&mut decoder).take(remaining + 1), &mut output)?,
+        None => io::copy(&mut decoder, &mut output)?,
+    };
+    limits.add_size(copied)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_from_name() {
+        assert_eq!(ArchiveFormat::from_name("a.zip"), Some(ArchiveFormat::Zip));
+        assert_eq!(ArchiveFormat::from_name("a.tar"), Some(ArchiveFormat::Tar(Codec::None)));
+        assert_eq!(ArchiveFormat::from_name("a.TGZ"), Some(ArchiveFormat::Tar(Codec::Gzip)));
+        assert_eq!(ArchiveFormat::from_name("a.tar.bz2"), Some(ArchiveFormat::Tar(Codec::Bzip2)));
+        assert_eq!(ArchiveFormat::from_name("a.json.xz"), Some(ArchiveFormat::Single(Codec::Xz)));
+        assert_eq!(ArchiveFormat::from_name("a.rar"), None);
+    }
+
+    #[test]
+    fn test_single_files_and_detection() {
+        let temp_dir = tempdir().unwrap();
+        let src = temp_dir.path().join("data.json");
+        std::fs::write(&src, "{ \"name\": \"lush\" }").unwrap();
+
+        for codec in [Codec::Gzip, Codec::Zstd, Codec::Xz, Codec::Bzip2] {
+            // No extension, so only the magic bytes tell the format
+            let compressed = temp_dir.path().join(format!("compressed-{:?}", codec));
+            compress_file(&src, &compressed, codec, &CompressOptions::default()).unwrap();
+            assert_eq!(ArchiveFormat::detect(&compressed).unwrap(), Some(ArchiveFormat::Single(codec)));
+
+            let named = temp_dir.path().join(format!("data.json{}", codec.extension()));
+            std::fs::rename(&compressed, &named).unwrap();
+            let out_dir = temp_dir.path().join(format!("out-{:?}", codec));
+            decompress_file(&named, &out_dir, codec, &ExtractOptions::default()).unwrap();
+            assert_eq!(std::fs::read(out_dir.join("data.json")).unwrap(), std::fs::read(&src).unwrap());
+        }
+
+        assert_eq!(ArchiveFormat::detect(&src).unwrap(), None);
+
+        let options = ExtractOptions { max_size: Some(5), max_entries: None, ..Default::default() };
+        let named = temp_dir.path().join("data.json.gz");
+        assert!(decompress_file(&named, &temp_dir.path().join("limited"), Codec::Gzip, &options).is_err());
+    }
+
+    /// Data compressing like text, with repetitions both close and far apart.
+    fn sample_data(size: usize) -> Vec<u8> {
+        let words = ["lush", "archive", "zstd", "tar", "compress", "level", "worker", "window", "\n"];
+        let mut data = Vec::with_capacity(size);
+        let mut seed: u64 = 42;
+        while data.len() < size {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            data.extend_from_slice(words[(seed >> 33) as usize % words.len()].as_bytes());
+            data.push(b' ');
+        }
+        data.truncate(size);
+        data
+    }
+
+    #[test]
+    fn test_zstd_options() {
+        let temp_dir = tempdir().unwrap();
+        let src = temp_dir.path().join("data.txt");
+        std::fs::write(&src, sample_data(1024 * 1024)).unwrap();
+        let dictionary = b"lush archive zstd tar compress level worker window".to_vec();
+
+        let options = CompressOptions { workers: 2, long_distance: true, level: Some(5), ..Default::default() };
+        let compressed = temp_dir.path().join("data.txt.zst");
+        compress_file(&src, &compressed, Codec::Zstd, &options).unwrap();
+        decompress_file(&compressed, &temp_dir.path().join("mt"), Codec::Zstd, &ExtractOptions::default()).unwrap();
+        assert_eq!(std::fs::read(temp_dir.path().join("mt/data.txt")).unwrap(), std::fs::read(&src).unwrap());
+
+        let options = CompressOptions { dictionary: Some(dictionary.clone()), ..Default::default() };
+        compress_file(&src, &compressed, Codec::Zstd, &options).unwrap();
+        assert!(decompress_file(&compressed, &temp_dir.path().join("no-dict"), Codec::Zstd, &ExtractOptions::default()).is_err());
+        let options = ExtractOptions { dictionary: Some(dictionary), ..Default::default() };
+        decompress_file(&compressed, &temp_dir.path().join("dict"), Codec::Zstd, &options).unwrap();
+        assert_eq!(std::fs::read(temp_dir.path().join("dict/data.txt")).unwrap(), std::fs::read(&src).unwrap());
+    }
+
+    /// Compares the throughput of zstd with one and several workers. Run with
+    /// `cargo test --release bench_zstd_workers -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn bench_zstd_workers() {
+        let temp_dir = tempdir().unwrap();
+        let src = temp_dir.path().join("data.txt");
+        let size = 256 * 1024 * 1024;
+        std::fs::write(&src, sample_data(size)).unwrap();
+        let workers = std::thread::available_parallelism().map(|n| n.get() as u32).unwrap_or(4);
+
+        for (name, options) in [
+            ("1 thread", CompressOptions::default()),
+            ("workers", CompressOptions { workers, ..Default::default() }),
+            ("workers + long", CompressOptions { workers, long_distance: true, ..Default::default() }),
+        ] {
+            let dest = temp_dir.path().join("data.txt.zst");
+            let start = std::time::Instant::now();
+            compress_file(&src, &dest, Codec::Zstd, &options).unwrap();
+            let elapsed = start.elapsed().as_secs_f64();
+            let ratio = std::fs::metadata(&dest).unwrap().len() as f64 / size as f64;
+            println!("{:>16}: {:8.1} MB/s, ratio {:.3}", name, size as f64 / elapsed / 1_000_000.0, ratio);
+        }
+    }
+}
@@ -1,2 +1,5 @@
-pub mod tar_zst;
+pub mod safe_extract;
+pub mod entries;
+pub mod format;
+pub mod tar;
 pub mod zip;
@@ -0,0 +1,290 @@
+use std::fs;
+use std::io;
+use std::io::ErrorKind;
+use std::path::{Component, Path, PathBuf};
+use filetime::FileTime;
+
+/// Limits applied when extracting an archive, to protect against zip bombs. No limit by default.
+#[derive(Debug, Default, Clone)]
+pub struct ExtractOptions {
+    /// Maximum total size of the extracted files, in bytes
+    pub max_size: Option<u64>,
+    /// Maximum number of entries in the archive
+    pub max_entries: Option<u64>,
+    /// Only extracts the entries matching one of the patterns, or inside a directory matching one. All the
+    /// entries if empty
+    pub only: Vec<glob::Pattern>,
+    /// The dictionary used to compress a zstd archive
+    pub dictionary: Option<Vec<u8>>,
+    pub preserve: Preserve,
+}
+
+/// The metadata of the entries restored when extracting an archive.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Preserve {
+    /// The whole mode, with the setuid, setgid and sticky bits. Otherwise only the read, write and execute bits
+    pub permissions: bool,
+    pub mtime: bool,
+    /// Owner and group, only when running as root. Not stored in zip archives
+    pub ownership: bool,
+    /// Extended attributes of tar archives
+    pub xattrs: bool,
+}
+
+impl Default for Preserve {
+    fn default() -> Self {
+        Self { permissions: true, mtime: true, ownership: false, xattrs: false }
+    }
+}
+
+impl Preserve {
+    pub const NAMES: [&'static str; 4] = ["permissions", "mtime", "ownership", "xattrs"];
+
+    /// Restores all the metadata, or none.
+    pub fn all(preserve: bool) -> Self {
+        Self { permissions: preserve, mtime: preserve, ownership: preserve, xattrs: preserve }
+    }
+
+    /// Restores the metadata in the list, E.g. `["permissions", "mtime"]`.
+    pub fn from_names(names: &[String]) -> io::Result<Self> {
+        let mut res = Self::all(false);
+        for name in names {
+            match name.as_str() {
+                "permissions" => res.permissions = true,
+                "mtime" => res.mtime = true,
+                "ownership" => res.ownership = true,
+                "xattrs" => res.xattrs = true,
+                _ => return Err(io::Error::new(ErrorKind::InvalidInput, format!("Unknown metadata {}, valid names \
+                    are: {}", name, Self::NAMES.join(", ")))),
+            }
+        }
+        Ok(res)
+    }
+
+    /// Ownership is only restored by root, other users can't give files away.
+    pub fn restore_ownership(&self) -> bool {
+        self.ownership && is_root()
+    }
+}
+
+#[cfg(unix)]
+fn is_root() -> bool {
+    // SAFETY: geteuid has no preconditions and can't fail
+    unsafe { libc::geteuid() == 0 }
+}
+
+#[cfg(not(unix))]
+fn is_root() -> bool {
+    false
+}
+
+/// Metadata of an entry restored after extracting it. Directories are restored after their content, that would
+/// change their mtime and could be read-only.
+pub(crate) struct EntryMetadata {
+    pub path: PathBuf,
+    pub mode: Option<u32>,
+    pub mtime: Option<FileTime>,
+    /// User and group ids
+    pub owner: Option<(u32, u32)>,
+}
+
+impl EntryMetadata {
+    pub fn restore(&self, preserve: &Preserve) -> io::Result<()> {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            if let Some((uid, gid)) = self.owner.filter(|_| preserve.restore_ownership()) {
+                std::os::unix::fs::chown(&self.path, Some(uid), Some(gid))?;
+            }
+            if let Some(mode) = self.mode {
+                let mode = if preserve.permissions { mode & 0o7777 } else { mode & 0o777 };
+                fs::set_permissions(&self.path, fs::Permissions::from_mode(mode))?;
+            }
+        }
+
+        if let Some(mtime) = self.mtime.filter(|_| preserve.mtime) {
+            filetime::set_file_mtime(&self.path, mtime)?;
+        }
+        Ok(())
+    }
+}
+
+impl ExtractOptions {
+    /// Returns true if the entry is selected by `only`.
+    pub fn is_selected(&self, name: &Path) -> bool {
+        self.only.is_empty() || name.ancestors()
+            .filter(|p| !p.as_os_str().is_empty())
+            .any(|p| self.only.iter().any(|pattern| pattern.matches_path(p)))
+    }
+}
+
+/// Keeps track of the size and number of extracted entries.
+pub(crate) struct ExtractLimits {
+    options: ExtractOptions,
+    size: u64,
+    entries: u64,
+}
+
+impl ExtractLimits {
+    pub fn new(options: &ExtractOptions) -> Self {
+        Self { options: options.clone(), size: 0, entries: 0 }
+    }
+
+    pub fn add_entry(&mut self) -> io::Result<()> {
+        self.entries += 1;
+        match self.options.max_entries {
+            Some(max) if self.entries > max => Err(limit_error(format!("more than {} entries", max))),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn add_size(&mut self, size: u64) -> io::Result<()> {
+        self.size = self.size.saturating_add(size);
+        match self.options.max_size {
+            Some(max) if self.size > max => Err(limit_error(format!("more than {} bytes", max))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Bytes that can still be extracted before reaching the size limit.
+    pub fn remaining_size(&self) -> Option<u64> {
+        self.options.max_size.map(|max| max.saturating_sub(self.size))
+    }
+}
+
+fn limit_error(msg: String) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, format!("Archive limit exceeded: {}", msg))
+}
+
+fn unsafe_error(msg: String) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, format!("Unsafe archive entry: {}", msg))
+}
+
+/// Checks that an entry name is a relative path without `..`, so it cannot be written outside the output
+/// directory.
+pub(crate) fn check_entry_name(name: &Path) -> io::Result<()> {
+    for component in name.components() {
+        match component {
+            Component::Normal(_) | Component::CurDir => {}
+            _ => return Err(unsafe_error(name.display().to_string())),
+        }
+    }
+    Ok(())
+}
+
+/// Checks that a symbolic link stored at `entry` (relative to `root`, which must be canonical) points inside
+/// `root`.
+///
+/// The target is followed from the real directory of the link, through the links already extracted, so a chain
+/// like `s -> .` then `s/l -> ..` is refused. `..` is only accepted at the start of the target, as in
+/// `../lib/app`: after a name, it could go back through a link, as `s/..` with `s -> .`.
+pub(crate) fn check_link_target(root: &Path, entry: &Path, target: &Path) -> io::Result<()> {
+    let unsafe_link = || unsafe_error(format!("{} links to {}", entry.display(), target.display()));
+    let mut dir = match root.join(entry).parent() {
+        Some(parent) => real_path(parent)?,
+        None => return Err(unsafe_link()),
+    };
+
+    let mut after_name = false;
+    for component in target.components() {
+        match component {
+            Component::ParentDir if !after_name => {
+                dir.pop();
+            }
+            Component::Normal(name) => {
+                after_name = true;
+                dir.push(name);
+            }
+            Component::CurDir => {}
+            _ => return Err(unsafe_link()),
+        }
+        if !dir.starts_with(root) {
+            return Err(unsafe_link());
+        }
+    }
+    Ok(())
+}
+
+/// The canonical path of the deepest existing ancestor of `path`, followed by the rest of `path`. Fails for
+/// dangling symbolic links.
+fn real_path(path: &Path) -> io::Result<PathBuf> {
+    let existing = path.ancestors().find(|p| fs::symlink_metadata(p).is_ok()).unwrap_or(path);
+    let rest = path.strip_prefix(existing).unwrap_or(Path::new(""));
+    Ok(existing.canonicalize()?.join(rest))
+}
+
+/// Checks that writing `path` does not go through a symbolic link leading outside `root`, which must be
+/// canonical.
+pub(crate) fn check_inside(root: &Path, path: &Path) -> io::Result<()> {
+    let existing = path.ancestors().find(|p| p.exists()).unwrap_or(root);
+    if !existing.canonicalize()?.starts_with(root) {
+        return Err(unsafe_error(format!("{} is outside of {}", path.display(), root.display())));
+    }
+    Ok(())
+}
+
+/// Creates the output directory, if needed, and returns its canonical path.
+pub(crate) fn output_root(output_dir: &Path) -> io::Result<PathBuf> {
+    fs::create_dir_all(output_dir)?;
+    output_dir.canonicalize()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_entry_name() {
+        assert!(check_entry_name(Path::new("dir/file.txt")).is_ok());
+        assert!(check_entry_name(Path::new("./file.txt")).is_ok());
+        assert!(check_entry_name(Path::new("../file.txt")).is_err());
+        assert!(check_entry_name(Path::new("dir/../../file.txt")).is_err());
+        assert!(check_entry_name(Path::new("/etc/passwd")).is_err());
+    }
+
+    #[test]
+    fn test_check_link_target() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let root = temp_dir.path().canonicalize().unwrap();
+        assert!(check_link_target(&root, Path::new("bin/app"), Path::new("../lib/app")).is_ok());
+        assert!(check_link_target(&root, Path::new("bin/app"), Path::new("../../etc")).is_err());
+        assert!(check_link_target(&root, Path::new("app"), Path::new("../app")).is_err());
+        assert!(check_link_target(&root, Path::new("app"), Path::new("/etc/passwd")).is_err());
+        assert!(check_link_target(&root, Path::new("app"), Path::new("lib/../../etc")).is_err());
+
+        // The directory of the link is followed through the links already extracted
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(".", root.join("s")).unwrap();
+            assert!(check_link_target(&root, Path::new("s/l"), Path::new("..")).is_err());
+            assert!(check_link_target(&root, Path::new("l"), Path::new("s/..")).is_err());
+            assert!(check_link_target(&root, Path::new("s/l"), Path::new("app")).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_is_selected() {
+        let options = ExtractOptions { only: vec![glob::Pattern::new("docs").unwrap(), glob::Pattern::new("*.md").unwrap()], ..Default::default() };
+        assert!(options.is_selected(Path::new("docs/guide/index.html")));
+        assert!(options.is_selected(Path::new("README.md")));
+        assert!(!options.is_selected(Path::new("src/main.rs")));
+        assert!(ExtractOptions::default().is_selected(Path::new("src/main.rs")));
+    }
+
+    #[test]
+    fn test_preserve() {
+        let preserve = Preserve::from_names(&["mtime".to_string()]).unwrap();
+        assert_eq!(preserve, Preserve { permissions: false, mtime: true, ownership: false, xattrs: false });
+        assert!(Preserve::from_names(&["acl".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_limits() {
+        let mut limits = ExtractLimits::new(&ExtractOptions { max_size: Some(10), max_entries: Some(1), ..Default::default() });
+        limits.add_entry().unwrap();
+        limits.add_size(8).unwrap();
+        assert_eq!(limits.remaining_size(), Some(2));
+        assert!(limits.add_size(3).is_err());
+        assert!(limits.add_entry().is_err());
+    }
+}
@@ -0,0 +1,237 @@
+use std::fs::File;
+use std::{fs, io};
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+use crate::compress::entries::{collect_entries, ArchiveEntry, CompressOptions, EntryKind};
+use crate::compress::format::Codec;
+use filetime::FileTime;
+use crate::compress::safe_extract::{check_entry_name, check_inside, check_link_target, output_root, EntryMetadata, ExtractLimits, ExtractOptions};
+
+/// Extracts a tar archive, compressed with `codec`.
+pub fn extract_tar_file(path: PathBuf, output_dir: PathBuf, codec: Codec, options: &ExtractOptions) -> io::Result<()> {
+    let file = File::open(path)?;
+    extract_tar(codec.decoder(file, options.dictionary.as_deref())?, output_dir, options)
+}
+
+/// Extracts a tar stream, refusing entries that would be written outside the output directory and links pointing
+/// outside of it. The metadata in `options.preserve` is restored.
+pub(crate) fn extract_tar<R: Read>(reader: R, output_dir: PathBuf, options: &ExtractOptions) -> io::Result<()> {
+    let preserve = &options.preserve;
+    let mut archive = tar::Archive::new(reader);
+    archive.set_preserve_permissions(preserve.permissions);
+    archive.set_preserve_mtime(preserve.mtime);
+    archive.set_preserve_ownerships(preserve.restore_ownership());
+    archive.set_unpack_xattrs(preserve.xattrs);
+    let root = output_root(&output_dir)?;
+    let mut limits = ExtractLimits::new(options);
+    let mut dirs = vec![];
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let name = entry.path()?.into_owned();
+        if !options.is_selected(&name) {
+            continue;
+        }
+        limits.add_entry()?;
+        limits.add_size(entry.size())?;
+        check_entry_name(&name)?;
+        check_inside(&root, &root.join(&name))?;
+
+        let entry_type = entry.header().entry_type();
+        if entry_type.is_symlink() || entry_type.is_hard_link() {
+            let target = entry.link_name()?.map(|t| t.into_owned()).unwrap_or_default();
+            if entry_type.is_symlink() {
+                check_link_target(&root, &name, &target)?;
+            } else {
+                // Hard link targets are relative to the archive root
+                check_entry_name(&target)?;
+                check_inside(&root, &root.join(&target))?;
+            }
+        }
+
+        if entry_type.is_dir() {
+            let path = root.join(&name);
+            fs::create_dir_all(&path)?;
+            let header = entry.header();
+            dirs.push(EntryMetadata {
+                path,
+                mode: header.mode().ok(),
+                mtime: header.mtime().ok().map(|t| FileTime::from_unix_time(t as i64, 0)),
+                owner: header.uid().ok().zip(header.gid().ok()).map(|(uid, gid)| (uid as u32, gid as u32)),
+            });
+            continue;
+        }
+
+        // unpack_in also validates the path, but silently skips the invalid entries
+        if !entry.unpack_in(&root)? {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                format!("Unsafe archive entry: {}", name.display())));
+        }
+    }
+
+    // Children before their parents
+    for dir in dirs.iter().rev() {
+        dir.restore(preserve)?;
+    }
+    Ok(())
+}
+
+/// Lists the entries of a tar archive, compressed with `codec`, without extracting them.
+pub fn list_tar(path: &Path, codec: Codec) -> io::Result<Vec<ArchiveEntry>> {
+    let mut archive = tar::Archive::new(codec.decoder(File::open(path)?, None)?);
+    let mut entries = vec![];
+    for entry in archive.entries()? {
+        let entry = entry?;
+        let header = entry.header();
+        entries.push(ArchiveEntry {
+            name: entry_name(&entry)?,
+            size: entry.size(),
+            compressed_size: if codec == Codec::None { Some(entry.size()) } else { None },
+            mode: header.mode().ok(),
+            mtime: header.mtime().ok().map(|t| t as i64),
+            is_dir: header.entry_type().is_dir(),
+        });
+    }
+    Ok(entries)
+}
+
+/// Reads the content of a file in a tar archive, compressed with `codec`.
+pub fn read_tar_entry(path: &Path, codec: Codec, name: &str) -> io::Result<Vec<u8>> {
+    let mut archive = tar::Archive::new(codec.decoder(File::open(path)?, None)?);
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if entry_name(&entry)? != name.trim_start_matches("./") {
+            continue;
+        }
+        if entry.header().entry_type().is_dir() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("{} is a directory", name)));
+        }
+        let mut content = vec![];
+        entry.read_to_end(&mut content)?;
+        return Ok(content);
+    }
+    Err(io::Error::new(io::ErrorKind::NotFound, format!("{} not found in {}", name, path.display())))
+}
+
+/// The name of an entry, with `/` as separator and without leading `./`.
+fn entry_name<R: Read>(entry: &tar::Entry<R>) -> io::Result<String> {
+    let path = entry.path()?;
+    Ok(path.components()
+        .filter(|c| !matches!(c, Component::CurDir))
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/"))
+}
+
+/// Creates a tar archive, compressed with `codec`. The names of the files in the archive are given by the options,
+/// as for zip archives.
+pub fn create_tar(tar_file: &PathBuf, src_files: &[PathBuf], codec: Codec, options: &CompressOptions) -> io::Result<()> {
+    let entries = collect_entries(src_files, options)?;
+    let out_file = File::create(tar_file)?;
+    let mut tar_builder = tar::Builder::new(codec.encoder(out_file, options)?);
+
+    for entry in entries {
+        match entry.kind {
+            EntryKind::File => tar_builder.append_path_with_name(&entry.path, &entry.name)?,
+            EntryKind::Dir => tar_builder.append_dir(&entry.name, &entry.path)?,
+            EntryKind::Symlink => {
+                let mut header = tar::Header::new_gnu();
+                header.set_metadata(&fs::symlink_metadata(&entry.path)?);
+                header.set_entry_type(tar::EntryType::Symlink);
+                header.set_size(0);
+                tar_builder.append_link(&mut header, &entry.name, fs::read_link(&entry.path)?)?;
+            }
+        }
+    }
+
+    // Finish both the tar and compression writers
+    tar_builder.into_inner()?.finish()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::{self, File};
+    use std::io::Write;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_create_and_extract_tar_zst() {
+        // Setup: create a temp dir with a file
+        let temp_dir = tempdir().unwrap();
+        let src_file_path = temp_dir.path().join("example.txt");
+        let mut file = File::create(&src_file_path).unwrap();
+        writeln!(file, "Hello from the tar.zst test!").unwrap();
+
+        // Path to .tar.zst file
+        let archive_path = temp_dir.path().join("archive.tar.zst");
+
+        // Create .tar.zst
+        create_tar(&archive_path, std::slice::from_ref(&src_file_path), Codec::Zstd, &CompressOptions::default()).unwrap();
+        assert!(archive_path.exists());
+
+        // Extract it to a different temp dir
+        let extract_dir = tempdir().unwrap();
+        extract_tar_file(archive_path.clone(), extract_dir.path().to_path_buf(), Codec::Zstd, &ExtractOptions::default()).unwrap();
+
+        // Check that the file exists and content is correct
+        let extracted_file_path = extract_dir.path().join("example.txt");
+        assert!(extracted_file_path.exists());
+
+        let content = fs::read_to_string(extracted_file_path).unwrap();
+        assert_eq!(content.trim(), "Hello from the tar.zst test!");
+    }
+
+    fn tar_with_entry(name: &[u8], entry_type: tar::EntryType, link: Option<&str>) -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.as_gnu_mut().unwrap().name[..name.len()].copy_from_slice(name);
+        header.set_entry_type(entry_type);
+        header.set_mode(0o644);
+        if let Some(link) = link {
+            header.set_link_name(link).unwrap();
+        }
+        let data: &[u8] = if entry_type == tar::EntryType::Regular { b"evil" } else { b"" };
+        header.set_size(data.len() as u64);
+        header.set_cksum();
+        builder.append(&header, data).unwrap();
+        builder.into_inner().unwrap()
+    }
+
+    #[test]
+    fn test_extract_refuses_unsafe_entries() {
+        let temp_dir = tempdir().unwrap();
+        let out_dir = temp_dir.path().join("out");
+        let options = ExtractOptions::default();
+
+        let tar = tar_with_entry(b"../evil.txt", tar::EntryType::Regular, None);
+        assert!(extract_tar(&tar[..], out_dir.clone(), &options).is_err());
+        assert!(!temp_dir.path().join("evil.txt").exists());
+
+        let tar = tar_with_entry(b"link", tar::EntryType::Symlink, Some("../../etc"));
+        assert!(extract_tar(&tar[..], out_dir.clone(), &options).is_err());
+        let tar = tar_with_entry(b"link", tar::EntryType::Symlink, Some("/etc"));
+        assert!(extract_tar(&tar[..], out_dir.clone(), &options).is_err());
+        let tar = tar_with_entry(b"hard", tar::EntryType::Link, Some("../secret"));
+        assert!(extract_tar(&tar[..], out_dir.clone(), &options).is_err());
+
+        // A link inside a link to the output directory: out/l -> .. would point outside
+        #[cfg(unix)]
+        {
+            let mut tar = tar_with_entry(b"s", tar::EntryType::Symlink, Some("."));
+            tar.truncate(512);
+            tar.extend(tar_with_entry(b"s/l", tar::EntryType::Symlink, Some("..")));
+            assert!(extract_tar(&tar[..], out_dir.clone(), &options).is_err());
+            assert!(fs::symlink_metadata(out_dir.join("l")).is_err());
+            fs::remove_file(out_dir.join("s")).unwrap();
+        }
+
+        let tar = tar_with_entry(b"good.txt", tar::EntryType::Regular, None);
+        extract_tar(&tar[..], out_dir.clone(), &options).unwrap();
+        assert!(out_dir.join("good.txt").exists());
+
+        let options = ExtractOptions { max_size: Some(2), max_entries: None, ..Default::default() };
+        assert!(extract_tar(&tar[..], out_dir, &options).is_err());
+    }
+}
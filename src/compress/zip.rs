@@ -1,149 +1,365 @@
 use std::fs::{File, metadata};
 use std::{fs, io};
-use std::io::{Read, Write};
+use std::io::Read;
 use std::os::unix::fs::PermissionsExt;
 use std::path::{Path, PathBuf};
-use std::time::SystemTime;
 use chrono::{Local, NaiveDateTime, TimeZone};
-use filetime::{set_file_mtime, FileTime};
+use filetime::FileTime;
 use zip::write::SimpleFileOptions;
 use zip::{ZipArchive, ZipWriter};
 use zip::result::ZipResult;
+use indicatif::ProgressBar;
+use crate::compress::entries::{collect_entries, path_error, ArchiveEntry, CompressOptions, EntryKind, SourceEntry};
+use crate::compress::safe_extract::{check_entry_name, check_inside, check_link_target, output_root, EntryMetadata, ExtractLimits, ExtractOptions};
+use crate::utils::progress::bytes_progress;
 
 /// Internal function to handle the zipping process.
 ///
 /// This function creates a ZIP file and writes the specified source files into it. It can handle both files and directories,
-/// zipping them recursively if specified.
+/// zipping them recursively if specified in the options.
 ///
 /// # Arguments
 ///
 /// * `zip_file` - The path to the ZIP file to create.
 /// * `src_files` - A slice of paths to source files to add to the ZIP archive.
-/// * `recurse` - A boolean indicating whether to zip directories recursively.
+/// * `options` - The compression options, including the names of the files in the archive.
 ///
 /// # Returns
 ///
 /// * An `io::Result` indicating success or an error if the zipping fails.
-pub(crate) fn create_zip_int(zip_file: &PathBuf, src_files: &[PathBuf], recurse: bool) -> io::Result<()> {
+pub(crate) fn create_zip_int(zip_file: &PathBuf, src_files: &[PathBuf], options: &CompressOptions) -> io::Result<()> {
+    let entries = collect_entries(src_files, options)?;
     let dest_file = File::create(zip_file)?;
     let mut writer = zip::ZipWriter::new(dest_file);
-    let mut buffer = Vec::new();
 
-    zip_list(src_files, &mut writer, &mut buffer, recurse)?;
+    // Only large archives show a progress bar, small ones are done before it would be seen
+    let total_size: u64 = entries.iter()
+        .filter(|e| e.kind == EntryKind::File)
+        .filter_map(|e| metadata(&e.path).ok())
+        .map(|md| md.len())
+        .sum();
+    let progress = match total_size >= PROGRESS_MIN_SIZE {
+        true => bytes_progress(Some(total_size), &zip_file.to_string_lossy()),
+        false => ProgressBar::hidden(),
+    };
+
+    zip_list(&entries, &mut writer, &progress, options)?;
 
     writer.finish()?;
+    progress.finish_and_clear();
     Ok(())
 }
 
-/// Writes the contents of the specified source files to the ZIP writer.
+/// Size of the files from which a progress bar is shown when creating a ZIP archive.
+const PROGRESS_MIN_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Writes the contents of the specified entries to the ZIP writer.
 ///
-/// This function handles both files and directories, adding them to the ZIP archive while preserving their structure.
+/// The files are streamed into the archive, so they are never fully loaded in memory. Permissions and modification
+/// times are stored for files and directories.
 ///
 /// # Arguments
 ///
-/// * `src_files` - A slice of paths to source files or directories to add to the ZIP archive.
+/// * `entries` - The files, directories and links to add to the ZIP archive, with their names.
 /// * `writer` - A mutable reference to the `ZipWriter` to write the files into.
-/// * `buffer` - A mutable reference to a buffer used for reading file contents.
-/// * `recurse` - A boolean indicating whether to add files in directories recursively.
+/// * `progress` - The progress bar advanced with the bytes of the files.
+/// * `options` - The compression level and method.
 ///
 /// # Returns
 ///
 /// * An `io::Result` indicating success or an error if the operation fails.
-fn zip_list(src_files: &[PathBuf], writer: &mut ZipWriter<File>, buffer: &mut Vec<u8>, recurse: bool) -> io::Result<()> {
-    for src_path in src_files.iter() {
-        let md = metadata(src_path).unwrap();
-        if md.is_file() {
-            let options = SimpleFileOptions::default()
-                .compression_method(zip::CompressionMethod::Deflated)
-                .unix_permissions(md.permissions().mode());
-
-            writer.start_file(src_path.to_str().unwrap(), options)?;
-            let mut f = File::open(src_path)?;
-            f.read_to_end(buffer)?;
-            writer.write_all(buffer)?;
-            buffer.clear();
-        } else if md.is_dir() {
-            let options = SimpleFileOptions::default()
-                .compression_method(zip::CompressionMethod::Deflated);
-            writer.add_directory(src_path.to_str().unwrap(), options)?;
-            if recurse {
-                let files: Vec<PathBuf> = fs::read_dir(src_path)?
-                    .map(|f| f.unwrap().path()).collect();
-
-                zip_list(&files, writer, buffer, recurse)?;
+fn zip_list(entries: &[SourceEntry], writer: &mut ZipWriter<File>, progress: &ProgressBar, options: &CompressOptions) -> io::Result<()> {
+    let method = if options.store_only { zip::CompressionMethod::Stored } else { zip::CompressionMethod::Deflated };
+    let default_options = SimpleFileOptions::default()
+        .compression_method(method)
+        .compression_level(if options.store_only { None } else { options.level.map(i64::from) });
+
+    for entry in entries.iter() {
+        let md = fs::symlink_metadata(&entry.path).map_err(|e| path_error(&entry.path, e))?;
+        let mut file_options = default_options;
+        if let Some(time) = zip_time(&md) {
+            file_options = file_options.last_modified_time(time);
+        }
+
+        match entry.kind {
+            EntryKind::File => {
+                // Symbolic links are followed when the entry is a file
+                let md = metadata(&entry.path).map_err(|e| path_error(&entry.path, e))?;
+                let f = File::open(&entry.path).map_err(|e| path_error(&entry.path, e))?;
+                writer.start_file(entry.name.as_str(), file_options.unix_permissions(md.permissions().mode()))?;
+                io::copy(&mut progress.wrap_read(f), writer)?;
+            }
+            EntryKind::Dir => {
+                writer.add_directory(entry.name.as_str(), file_options.unix_permissions(md.permissions().mode()))?;
+            }
+            EntryKind::Symlink => {
+                let target = fs::read_link(&entry.path)?;
+                writer.add_symlink(entry.name.as_str(), target.to_string_lossy(), file_options)?;
             }
         }
     }
     Ok(())
 }
 
+/// The modification time of a file, in the local time as stored in ZIP archives. `None` if it can't be stored,
+/// E.g. before 1980.
+fn zip_time(md: &fs::Metadata) -> Option<zip::DateTime> {
+    let modified: chrono::DateTime<Local> = md.modified().ok()?.into();
+    zip::DateTime::try_from(modified.naive_local()).ok()
+}
+
 /// Internal function to handle the extraction of ZIP archives.
 ///
 /// This function reads the contents of a ZIP file and extracts them to the specified output directory.
+/// Entries with absolute paths or `..`, or that would be written through a symbolic link outside the output
+/// directory, are refused.
 ///
 /// # Arguments
 ///
 /// * `path` - The path to the ZIP file to extract.
 /// * `output_dir` - The directory where the contents will be extracted.
+/// * `options` - Size and entry count limits, and the metadata to restore.
 ///
 /// # Returns
 ///
 /// * A `ZipResult` indicating success or an error if the extraction fails.
-pub(crate) fn extract_zip_int(path: PathBuf, output_dir: PathBuf) -> ZipResult<()> {
+pub(crate) fn extract_zip_int(path: PathBuf, output_dir: PathBuf, options: &ExtractOptions) -> ZipResult<()> {
     let zip_file = File::open(&path)?;
     let mut archive = ZipArchive::new(zip_file)?;
+    let root = output_root(&output_dir)?;
+    let mut limits = ExtractLimits::new(options);
+    let mut dirs = vec![];
 
     for i in 0..archive.len() {
         let mut file = archive.by_index(i)?;
-        let out_path = output_dir.join(file.name());
+        let name = match file.enclosed_name() {
+            Some(name) => name,
+            None => return Err(io::Error::new(io::ErrorKind::InvalidData,
+                format!("Unsafe archive entry: {}", file.name())).into()),
+        };
+        if !options.is_selected(&name) {
+            continue;
+        }
+        limits.add_entry()?;
+        check_entry_name(&name)?;
+        let out_path = root.join(&name);
+        check_inside(&root, &out_path)?;
 
-        if (*file.name()).ends_with('/') {
+        let metadata = EntryMetadata {
+            path: out_path.clone(),
+            mode: file.unix_mode(),
+            mtime: file.last_modified()
+                .and_then(|dt| NaiveDateTime::try_from(dt).ok())
+                .and_then(local_naive_to_filetime),
+            owner: None,
+        };
+
+        if file.is_dir() {
             fs::create_dir_all(&out_path)?;
-        } else {
-            if let Some(p) = out_path.parent() {
-                if !p.exists() {
-                    fs::create_dir_all(p)?;
-                }
-            }
-            let mut outfile = File::create(&out_path)?;
-            std::io::copy(&mut file, &mut outfile)?;
-            drop(outfile);
+            // Extracting the files changes the mtime of the directories, and they may be read-only
+            dirs.push(metadata);
+            continue;
         }
 
-        // Restore permissions (if available)
-        #[cfg(unix)]
-        if let Some(mode) = file.unix_mode() {
-            set_permissions(&out_path, mode)?;
+        if let Some(p) = out_path.parent() {
+            if !p.exists() {
+                fs::create_dir_all(p)?;
+            }
+        }
+        // Replaces a symbolic link instead of writing to its target
+        if fs::symlink_metadata(&out_path).is_ok_and(|md| md.file_type().is_symlink()) {
+            fs::remove_file(&out_path)?;
         }
 
-        if let Some(last_mod) = file.last_modified() {
-            if let Ok(dt) = NaiveDateTime::try_from(last_mod) {
-                let _ = set_mtime_from_local_naive(&out_path, dt);
+        if file.is_symlink() {
+            let mut target = String::new();
+            (&mut file).take(MAX_LINK_SIZE).read_to_string(&mut target)?;
+            check_link_target(&root, &name, Path::new(&target))?;
+            if out_path.exists() {
+                fs::remove_file(&out_path)?;
             }
-
+            std::os::unix::fs::symlink(&target, &out_path)?;
+            continue;
         }
+
+        let mut outfile = File::create(&out_path)?;
+        // The sizes in the zip headers can't be trusted, so the limit is checked on the data read
+        let copied = match limits.remaining_size() {
+            Some(remaining) => io::copy(&mut (&mut file).take(remaining + 1), &mut outfile)?,
+            None => io::copy(&mut file, &mut outfile)?,
+        };
+        limits.add_size(copied)?;
+        drop(outfile);
+        metadata.restore(&options.preserve)?;
     }
 
+    // Children before their parents
+    for dir in dirs.iter().rev() {
+        dir.restore(&options.preserve)?;
+    }
 
     Ok(())
 }
 
-fn set_mtime_from_local_naive(path: &Path, naive: NaiveDateTime) -> std::io::Result<()> {
-    // Interpret NaiveDateTime as local time
-    let local_dt = Local.from_local_datetime(&naive)
-        .single()
-        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, "Ambiguous or nonexistent local time"))?;
+/// Maximum length of the target of a symbolic link.
+const MAX_LINK_SIZE: u64 = 4096;
 
-    // Convert to SystemTime
-    let system_time: SystemTime = local_dt.into();
+/// Lists the entries of a ZIP archive, without extracting them.
+pub(crate) fn list_zip(path: &Path) -> ZipResult<Vec<ArchiveEntry>> {
+    let mut archive = ZipArchive::new(File::open(path)?)?;
+    let mut entries = vec![];
+    for i in 0..archive.len() {
+        let file = archive.by_index_raw(i)?;
+        entries.push(ArchiveEntry {
+            name: file.name().to_string(),
+            size: file.size(),
+            compressed_size: Some(file.compressed_size()),
+            mode: file.unix_mode(),
+            mtime: file.last_modified()
+                .and_then(|dt| NaiveDateTime::try_from(dt).ok())
+                .and_then(|dt| Local.from_local_datetime(&dt).single())
+                .map(|dt| dt.timestamp()),
+            is_dir: file.is_dir(),
+        });
+    }
+    Ok(entries)
+}
 
-    // Convert to FileTime and set mtime
-    let file_time = FileTime::from_system_time(system_time);
-    set_file_mtime(path, file_time)
+/// Reads the content of a file in a ZIP archive.
+pub(crate) fn read_zip_entry(path: &Path, name: &str) -> ZipResult<Vec<u8>> {
+    let mut archive = ZipArchive::new(File::open(path)?)?;
+    let mut file = archive.by_name(name)?;
+    if file.is_dir() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("{} is a directory", name)).into());
+    }
+    let mut content = vec![];
+    file.read_to_end(&mut content)?;
+    Ok(content)
 }
 
-#[cfg(unix)]
-fn set_permissions(path: &PathBuf, mode: u32) -> io::Result<()> {
-    use std::fs::Permissions;
-    fs::set_permissions(path, Permissions::from_mode(mode))
+/// Converts a time of a zip archive, in local time, to a file time.
+fn local_naive_to_filetime(naive: NaiveDateTime) -> Option<FileTime> {
+    // Interpret NaiveDateTime as local time, None if ambiguous or nonexistent
+    let local_dt = Local.from_local_datetime(&naive).single()?;
+    Some(FileTime::from_system_time(local_dt.into()))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use filetime::set_file_mtime;
+    use tempfile::tempdir;
+
+    fn write_zip(path: &Path, entries: &[(&str, &[u8])]) {
+        let mut writer = ZipWriter::new(File::create(path).unwrap());
+        for (name, data) in entries {
+            writer.start_file(*name, SimpleFileOptions::default()).unwrap();
+            writer.write_all(data).unwrap();
+        }
+        writer.finish().unwrap();
+    }
+
+    #[test]
+    fn test_extract_refuses_path_traversal() {
+        let temp_dir = tempdir().unwrap();
+        let out_dir = temp_dir.path().join("out");
+        for name in ["../evil.txt", "/tmp/evil.txt", "dir/../../evil.txt"] {
+            let zip_path = temp_dir.path().join("evil.zip");
+            write_zip(&zip_path, &[("good.txt", b"good"), (name, b"evil")]);
+            assert!(extract_zip_int(zip_path, out_dir.clone(), &ExtractOptions::default()).is_err(), "{}", name);
+        }
+        assert!(!temp_dir.path().join("evil.txt").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_extract_refuses_symlink_escape() {
+        let temp_dir = tempdir().unwrap();
+        let out_dir = temp_dir.path().join("out");
+        let outside = temp_dir.path().join("outside");
+        fs::create_dir_all(&out_dir).unwrap();
+        fs::create_dir_all(&outside).unwrap();
+        std::os::unix::fs::symlink(&outside, out_dir.join("link")).unwrap();
+
+        let zip_path = temp_dir.path().join("evil.zip");
+        write_zip(&zip_path, &[("link/evil.txt", b"evil")]);
+        assert!(extract_zip_int(zip_path, out_dir, &ExtractOptions::default()).is_err());
+        assert!(!outside.join("evil.txt").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_extract_refuses_symlink_chain() {
+        let temp_dir = tempdir().unwrap();
+        let out_dir = temp_dir.path().join("out");
+        let zip_path = temp_dir.path().join("evil.zip");
+        let mut writer = ZipWriter::new(File::create(&zip_path).unwrap());
+        writer.add_symlink("s", ".", SimpleFileOptions::default()).unwrap();
+        writer.add_symlink("s/l", "..", SimpleFileOptions::default()).unwrap();
+        writer.finish().unwrap();
+
+        assert!(extract_zip_int(zip_path, out_dir.clone(), &ExtractOptions::default()).is_err());
+        assert!(fs::symlink_metadata(out_dir.join("l")).is_err());
+    }
+
+    #[test]
+    fn test_list_and_read_entry() {
+        let temp_dir = tempdir().unwrap();
+        let zip_path = temp_dir.path().join("docs.zip");
+        write_zip(&zip_path, &[("README.md", b"readme"), ("src/main.rs", b"fn main() {}")]);
+
+        let entries = list_zip(&zip_path).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[1].name, "src/main.rs");
+        assert_eq!(entries[1].size, 12);
+        assert!(!entries[1].is_dir);
+
+        assert_eq!(read_zip_entry(&zip_path, "README.md").unwrap(), b"readme");
+        assert!(read_zip_entry(&zip_path, "missing.txt").is_err());
+
+        let options = ExtractOptions { only: vec![glob::Pattern::new("src").unwrap()], ..Default::default() };
+        extract_zip_int(zip_path, temp_dir.path().join("out"), &options).unwrap();
+        assert!(temp_dir.path().join("out/src/main.rs").exists());
+        assert!(!temp_dir.path().join("out/README.md").exists());
+    }
+
+    #[test]
+    fn test_create_preserves_metadata() {
+        let temp_dir = tempdir().unwrap();
+        let src = temp_dir.path().join("src");
+        fs::create_dir_all(src.join("sub")).unwrap();
+        fs::write(src.join("sub/data.txt"), "data").unwrap();
+        let mtime = FileTime::from_unix_time(1_600_000_000, 0);
+        set_file_mtime(src.join("sub"), mtime).unwrap();
+
+        let zip_path = temp_dir.path().join("src.zip");
+        create_zip_int(&zip_path, std::slice::from_ref(&src), &CompressOptions::default()).unwrap();
+        let out_dir = temp_dir.path().join("out");
+        extract_zip_int(zip_path.clone(), out_dir.clone(), &ExtractOptions::default()).unwrap();
+
+        assert_eq!(fs::read_to_string(out_dir.join("src/sub/data.txt")).unwrap(), "data");
+        let extracted = FileTime::from_last_modification_time(&fs::metadata(out_dir.join("src/sub")).unwrap());
+        assert_eq!(extracted.unix_seconds(), mtime.unix_seconds());
+
+        let err = create_zip_int(&zip_path, &[temp_dir.path().join("missing")], &CompressOptions::default()).unwrap_err();
+        assert!(err.to_string().contains("missing"));
+    }
+
+    #[test]
+    fn test_extract_limits() {
+        let temp_dir = tempdir().unwrap();
+        let zip_path = temp_dir.path().join("bomb.zip");
+        let data = vec![0u8; 10_000];
+        write_zip(&zip_path, &[("a.bin", &data), ("b.bin", &data)]);
+
+        let options = ExtractOptions { max_size: None, max_entries: Some(1), ..Default::default() };
+        assert!(extract_zip_int(zip_path.clone(), temp_dir.path().join("out1"), &options).is_err());
+        let options = ExtractOptions { max_size: Some(15_000), max_entries: None, ..Default::default() };
+        assert!(extract_zip_int(zip_path.clone(), temp_dir.path().join("out2"), &options).is_err());
+        let options = ExtractOptions { max_size: Some(20_000), max_entries: Some(2), ..Default::default() };
+        extract_zip_int(zip_path, temp_dir.path().join("out3"), &options).unwrap();
+        assert_eq!(fs::read(temp_dir.path().join("out3/b.bin")).unwrap(), data);
+    }
+}
+
@@ -5,6 +5,7 @@ use rustyline::validate::{ValidationContext, ValidationResult, Validator};
 use rustyline::completion::Completer;
 use std::borrow::Cow;
 use colored::*;
+use crate::repl::is_complete_statement;
 
 #[derive(Default)]
 pub struct LushHighlighter;
@@ -38,13 +39,39 @@ impl Validator for LushHighlighter {
     }
 
     fn validate(&self, ctx: &mut ValidationContext) -> rustyline::Result<ValidationResult> {
-        let _ = ctx;
-        Ok(ValidationResult::Valid(None))
+        Ok(validate_input(ctx.input()))
+    }
+}
+
+/// Checks if the input can be evaluated or if the user is still typing a multi-line statement.
+///
+/// `exit` and meta-commands (starting with `:`) are not Lua code, so they are always valid.
+fn validate_input(input: &str) -> ValidationResult {
+    let trimmed = input.trim();
+    if trimmed == "exit" || trimmed.starts_with(':') || is_complete_statement(input) {
+        ValidationResult::Valid(None)
+    } else {
+        ValidationResult::Incomplete
     }
-    
 }
 
 impl Completer for LushHighlighter {
     type Candidate = String;
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_input() {
+        assert!(matches!(validate_input("print('hello')"), ValidationResult::Valid(None)));
+        assert!(matches!(validate_input("exit"), ValidationResult::Valid(None)));
+        assert!(matches!(validate_input(":time if"), ValidationResult::Valid(None)));
+        assert!(matches!(validate_input("function f()\n  return 1\nend"), ValidationResult::Valid(None)));
+
+        assert!(matches!(validate_input("function f()"), ValidationResult::Incomplete));
+        assert!(matches!(validate_input("local t = {\n  x = 1,"), ValidationResult::Incomplete));
+    }
+}
@@ -6,6 +6,8 @@ mod string_utils;
 mod compress;
 mod utils;
 mod repl;
+mod repl_commands;
+mod repl_prompt;
 mod modules;
 mod lush_highlighter;
 mod preprocessor;
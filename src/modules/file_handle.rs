@@ -0,0 +1,424 @@
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io;
+use std::thread;
+use std::time::{Duration, Instant};
+use std::io::{BufRead, BufReader, ErrorKind, Read, Seek, SeekFrom, Write};
+use mlua::{AnyUserData, Lua, MetaMethod, Table, UserData, UserDataMethods, Value};
+use crate::utils::duration::seconds_option;
+
+/// A file opened by `fs.open`.
+///
+/// Reads are buffered. Before writing or seeking, the buffer is discarded, so the file position is always
+/// the one seen by the script.
+pub(crate) struct LushFile {
+    path: String,
+    reader: Option<BufReader<File>>,
+}
+
+impl LushFile {
+    fn reader(&mut self) -> io::Result<&mut BufReader<File>> {
+        match self.reader {
+            Some(ref mut reader) => Ok(reader),
+            None => Err(io::Error::other(format!("File {} is closed", self.path))),
+        }
+    }
+
+    /// Reads a line without its end of line. Lines are bytes, they don't need to be valid UTF-8.
+    fn read_line(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let mut line = vec![];
+        if self.reader()?.read_until(b'\n', &mut line)? == 0 {
+            return Ok(None);
+        }
+        if line.ends_with(b"\n") {
+            line.pop();
+            if line.ends_with(b"\r") {
+                line.pop();
+            }
+        }
+        Ok(Some(line))
+    }
+
+    /// Reads up to `count` bytes. Returns `None` at the end of the file, also for `count` 0, as `read(n)` in Lua.
+    fn read_bytes(&mut self, count: usize) -> io::Result<Option<Vec<u8>>> {
+        let reader = self.reader()?;
+        if reader.fill_buf()?.is_empty() {
+            return Ok(None);
+        }
+        // The count can be much larger than the file, E.g. f:read(math.maxinteger)
+        let mut buf = Vec::with_capacity(count.min(64 * 1024));
+        reader.take(count as u64).read_to_end(&mut buf)?;
+        Ok(Some(buf))
+    }
+
+    fn read_all(&mut self) -> io::Result<Vec<u8>> {
+        let mut buf = vec![];
+        self.reader()?.read_to_end(&mut buf)?;
+        Ok(buf)
+    }
+
+    fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        let reader = self.reader()?;
+        // Seeking discards the read buffer, moving the file position to where the script is
+        let pos = reader.stream_position()?;
+        reader.seek(SeekFrom::Start(pos))?;
+        reader.get_mut().write_all(data)
+    }
+
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.reader()?.seek(pos)
+    }
+
+    fn close(&mut self) -> io::Result<()> {
+        if let Some(reader) = self.reader.take() {
+            reader.into_inner().flush()?;
+        }
+        Ok(())
+    }
+}
+
+impl UserData for LushFile {
+    fn add_methods<M: UserDataMethods<Self>>(methods: &mut M) {
+        methods.add_function("lines", |lua, ud: AnyUserData| {
+            lua.create_function_mut(move |lua, ()| {
+                let mut file = ud.borrow_mut::<LushFile>()?;
+                file.read_line()?.map(|l| lua.create_string(l)).transpose()
+            })
+        });
+
+        methods.add_method_mut("read", |lua, this, format: Option<Value>| {
+            let res = match format {
+                None => this.read_line()?,
+                Some(Value::Integer(n)) => this.read_bytes(n.max(0) as usize)?,
+                Some(Value::Number(n)) => this.read_bytes(n.max(0.0) as usize)?,
+                Some(Value::String(s)) => match s.to_str()?.trim_start_matches('*') {
+                    "l" | "line" => this.read_line()?,
+                    "a" | "all" => Some(this.read_all()?),
+                    f => return Err(mlua::Error::RuntimeError(format!("Invalid read format {}", f))),
+                },
+                Some(f) => return Err(mlua::Error::RuntimeError(format!("Invalid read format {:?}", f))),
+            };
+            match res {
+                Some(bytes) => Ok(Value::String(lua.create_string(bytes)?)),
+                None => Ok(Value::Nil),
+            }
+        });
+
+        methods.add_function("write", |_, (ud, data): (AnyUserData, mlua::Variadic<mlua::String>)| {
+            {
+                let mut file = ud.borrow_mut::<LushFile>()?;
+                for s in data.iter() {
+                    file.write(&s.as_bytes())?;
+                }
+            }
+            Ok(ud)
+        });
+
+        methods.add_function("append", |_, (ud, data): (AnyUserData, mlua::String)| {
+            {
+                let mut file = ud.borrow_mut::<LushFile>()?;
+                file.seek(SeekFrom::End(0))?;
+                file.write(&data.as_bytes())?;
+            }
+            Ok(ud)
+        });
+
+        methods.add_method_mut("seek", |_, this, (whence, offset): (Option<String>, Option<i64>)| {
+            let offset = offset.unwrap_or(0);
+            let pos = match whence.as_deref().unwrap_or("cur") {
+                "set" => SeekFrom::Start(offset.max(0) as u64),
+                "cur" => SeekFrom::Current(offset),
+                "end" => SeekFrom::End(offset),
+                w => return Err(mlua::Error::RuntimeError(format!("Invalid seek whence {}", w))),
+            };
+            Ok(this.seek(pos)?)
+        });
+
+        methods.add_method_mut("flush", |_, this, ()| {
+            this.reader()?.get_mut().flush()?;
+            Ok(())
+        });
+
+        methods.add_method_mut("close", |_, this, ()| {
+            this.close()?;
+            Ok(())
+        });
+
+        // Allows `local f <close> = fs.open(...)`
+        methods.add_meta_method_mut(MetaMethod::Close, |_, this, _: Value| {
+            this.close()?;
+            Ok(())
+        });
+    }
+}
+
+/// Opens a file, returning a handle to read and write it.
+///
+/// # Arguments
+///
+/// * `_lua` - The Lua state (not used in this function).
+/// * `path` - The file path.
+/// * `mode` - Optional mode, as in C `fopen`: `r` (default), `w`, `a`, `r+`, `w+` or `a+`. A `b` is accepted and
+///   ignored.
+///
+/// # Returns
+///
+/// * The file handle, or `nil` and the error message if the file cannot be opened.
+///
+/// # Example (in Lua)
+///
+/// ```lua
+/// local f <close> = fs.open("/var/log/app.log")
+/// for line in f:lines() do
+///     print(line)
+/// end
+/// ```
+pub(crate) fn open(_lua: &Lua, (path, mode): (String, Option<String>)) -> mlua::Result<(Option<LushFile>, Option<String>)> {
+    let mode = mode.unwrap_or("r".to_string()).replace('b', "");
+    let mut options = OpenOptions::new();
+    match mode.as_str() {
+        "r" => options.read(true),
+        "w" => options.write(true).create(true).truncate(true),
+        "a" => options.append(true).create(true),
+        "r+" => options.read(true).write(true),
+        "w+" => options.read(true).write(true).create(true).truncate(true),
+        "a+" => options.read(true).append(true).create(true),
+        _ => return Err(io::Error::new(ErrorKind::InvalidInput, format!("Invalid mode {}", mode)).into()),
+    };
+
+    match options.open(&path) {
+        Ok(file) => Ok((Some(LushFile { path, reader: Some(BufReader::new(file)) }), None)),
+        Err(e) => Ok((None, Some(format!("{}: {}", path, e)))),
+    }
+}
+
+/// An advisory lock acquired by `fs.lock`. Released by `unlock`, when closed or when garbage collected.
+pub(crate) struct LushLock {
+    file: Option<File>,
+}
+
+impl LushLock {
+    fn unlock(&mut self) -> io::Result<()> {
+        if let Some(file) = self.file.take() {
+            file.unlock()?;
+        }
+        Ok(())
+    }
+}
+
+impl UserData for LushLock {
+    fn add_methods<M: UserDataMethods<Self>>(methods: &mut M) {
+        methods.add_method_mut("unlock", |_, this, ()| {
+            this.unlock()?;
+            Ok(())
+        });
+
+        // Allows `local lock <close> = fs.lock(...)`
+        methods.add_meta_method_mut(MetaMethod::Close, |_, this, _: Value| {
+            this.unlock()?;
+            Ok(())
+        });
+    }
+}
+
+const LOCK_RETRY_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Acquires an advisory lock on a file, creating it if it does not exist.
+///
+/// Advisory locks only work between processes that also lock the file, E.g. two lush scripts run by cron.
+///
+/// # Arguments
+///
+/// * `_lua` - The Lua state (not used in this function).
+/// * `path` - The file to lock.
+/// * `options` - Optional table containing:
+///   - `timeout` (number): Seconds to wait for the lock. 0 tries only once. Default waits forever.
+///   - `shared` (bool): Acquires a shared (read) lock instead of an exclusive one. Default false.
+///
+/// # Returns
+///
+/// * The lock, or `nil` and the error message if the lock was not acquired in time.
+///
+/// # Example (in Lua)
+///
+/// ```lua
+/// local lock <close>, err = fs.lock("/tmp/backup.lock", { timeout = 10 })
+/// if not lock then
+///     error("Another backup is running: " .. err)
+/// end
+/// ```
+pub(crate) fn lock(_lua: &Lua, (path, options): (String, Option<Table>)) -> mlua::Result<(Option<LushLock>, Option<String>)> {
+    let mut timeout = None;
+    let mut shared = false;
+    if let Some(ref tb) = options {
+        timeout = seconds_option(tb, "timeout")?;
+        shared = tb.get::<Option<bool>>("shared")?.unwrap_or(false);
+    }
+
+    let file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(&path)?;
+    // Deadlines too far to be represented wait forever
+    let deadline = timeout.and_then(|t| Instant::now().checked_add(t));
+
+    loop {
+        let res = if shared { file.try_lock_shared() } else { file.try_lock() };
+        match res {
+            Ok(()) => return Ok((Some(LushLock { file: Some(file) }), None)),
+            Err(TryLockError::WouldBlock) => {}
+            Err(TryLockError::Error(e)) => return Err(e.into()),
+        }
+
+        if deadline.is_some_and(|d| Instant::now() >= d) {
+            return Ok((None, Some(format!("Timeout waiting for the lock on {}", path))));
+        }
+        thread::sleep(LOCK_RETRY_INTERVAL);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+    use crate::setup::run_script;
+
+    #[test]
+    fn test_file_handle() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let file = temp_dir.path().join("test.log");
+
+        let script = format!(r#"
+            local path = "{}"
+            local f = fs.open(path, "w")
+            f:write("line 1\n", "line 2\n")
+            f:close()
+            assert(not pcall(function() f:write("closed") end))
+
+            local f <close> = fs.open(path, "a+")
+            f:append("line 3\n")
+            f:seek("set")
+            local lines = {{}}
+            for line in f:lines() do
+                lines[#lines + 1] = line
+            end
+            assert(#lines == 3)
+            assert(lines[3] == "line 3")
+
+            assert(f:seek("set", 5) == 5)
+            assert(f:read(1) == "1")
+            assert(f:read() == "")
+            assert(f:read("l") == "line 2")
+            assert(f:read("a") == "line 3\n")
+            assert(f:read(1) == nil)
+            assert(f:read(0) == nil)
+
+            -- The usual loop reading chunks until nil
+            f:seek("set")
+            local chunks = {{}}
+            while true do
+                local chunk = f:read(4)
+                if not chunk then break end
+                chunks[#chunks + 1] = chunk
+            end
+            assert(table.concat(chunks) == "line 1\nline 2\nline 3\n")
+            assert(#chunks == 6)
+
+            local missing, err = fs.open(path .. ".missing")
+            assert(missing == nil)
+            assert(err ~= nil)
+        "#, file.display());
+
+        run_script(&script, PathBuf::from("test.lua"), vec![]).unwrap();
+        assert_eq!(fs::read_to_string(file).unwrap(), "line 1\nline 2\nline 3\n");
+    }
+
+    #[test]
+    fn test_read_non_utf8_lines() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let file = temp_dir.path().join("latin1.log");
+        fs::write(&file, b"caf\xe9\r\nok\n").unwrap();
+
+        let script = format!(r#"
+            local f <close> = fs.open("{}")
+            local lines = {{}}
+            for line in f:lines() do
+                lines[#lines + 1] = line
+            end
+            assert(#lines == 2)
+            assert(lines[1] == "caf\xe9")
+            assert(lines[2] == "ok")
+        "#, file.display());
+
+        run_script(&script, PathBuf::from("test.lua"), vec![]).unwrap();
+    }
+
+    #[test]
+    fn test_read_write_append_file() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let file = temp_dir.path().join("test.txt");
+
+        let script = format!(r#"
+            local path = "{}"
+            assert(fs.write_file(path, "hello"))
+            assert(fs.append_file(path, " world"))
+            assert(fs.read_file(path) == "hello world")
+
+            local content, err = fs.read_file(path .. ".missing")
+            assert(content == nil)
+            assert(err ~= nil)
+
+            local ok, err = fs.write_file(path .. "/invalid/file", "x")
+            assert(ok == false)
+            assert(err ~= nil)
+        "#, file.display());
+
+        run_script(&script, PathBuf::from("test.lua"), vec![]).unwrap();
+    }
+
+    #[test]
+    fn test_lock() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let file = temp_dir.path().join("job.lock");
+
+        let script = format!(r#"
+            local path = "{}"
+            local lock, err = fs.lock(path, {{ timeout = 0 }})
+            assert(lock ~= nil and err == nil)
+
+            -- A second handle of the same file does not get the lock
+            local lock2, err = fs.lock(path, {{ timeout = 0.1 }})
+            assert(lock2 == nil)
+            assert(err ~= nil)
+
+            lock:unlock()
+            do
+                local lock3 <close> = fs.lock(path, {{ timeout = 0 }})
+                assert(lock3 ~= nil)
+            end
+
+            local s1 = fs.lock(path, {{ shared = true, timeout = 0 }})
+            local s2 = fs.lock(path, {{ shared = true, timeout = 0 }})
+            assert(s1 ~= nil and s2 ~= nil)
+
+            assert(not pcall(fs.lock, path, {{ timeout = math.huge }}))
+        "#, file.display());
+
+        run_script(&script, PathBuf::from("test.lua"), vec![]).unwrap();
+    }
+
+    #[test]
+    fn test_atomic_save() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let dir = temp_dir.path().display();
+
+        let script = format!(r#"
+            local dir = "{}"
+            assert(fs.write_file(dir .. "/file.txt", "content", {{ atomic = true }}))
+            json.save_file(dir .. "/file.json", {{ name = "lush" }}, {{ atomic = true }})
+            toml.save_file(dir .. "/file.toml", {{ name = "lush" }}, {{ atomic = true }})
+            assert(fs.read_file(dir .. "/file.txt") == "content")
+            assert(json.load_file(dir .. "/file.json").name == "lush")
+            assert(toml.load_file(dir .. "/file.toml").name == "lush")
+        "#, dir);
+
+        run_script(&script, PathBuf::from("test.lua"), vec![]).unwrap();
+        assert_eq!(fs::read_dir(temp_dir.path()).unwrap().count(), 3);
+    }
+}
@@ -1,22 +1,43 @@
 use std::io;
 use std::io::ErrorKind;
-use std::path::PathBuf;
-use mlua::{Lua, Value, Variadic};
-use crate::compress::tar_zst::{create_tar_zst, extract_tar_zst};
-use crate::compress::zip::{create_zip_int, extract_zip_int};
+use std::path::{Path, PathBuf};
+use mlua::{Lua, Table, Value, Variadic};
+use zip::result::ZipError;
+use crate::compress::entries::CompressOptions;
+use crate::compress::safe_extract::{ExtractOptions, Preserve};
+use crate::compress::format::{compress_file, decompress_file, ArchiveFormat, SUPPORTED_EXTENSIONS};
+use crate::compress::tar::{create_tar, extract_tar_file, list_tar, read_tar_entry};
+use crate::compress::zip::{create_zip_int, extract_zip_int, list_zip, read_zip_entry};
+use crate::modules::filesystem::glob_patterns;
 
 /// Compresses the specified files into a compressed archive.
 ///
 /// This function takes the name of the compressed file to create and a variadic list of file paths to add to the archive.
 /// It calls a helper function to handle the actual zipping process.
-/// Currently, only zip and tar.zst are supported
+/// The format is chosen by the extension: zip, tar (optionally compressed with gzip, zstd, xz or bzip2) or a
+/// single file compressed with one of them, E.g. `.gz`.
+///
+/// Zip and tar archives store each file or directory with its name at the root of the archive, unless `base_dir`
+/// or `strip_prefix` is given.
 ///
 /// # Arguments
 ///
 /// * `_lua` - The Lua state (not used in this function).
 /// * `(dest_file_name, files_to_add)` - A tuple where:
 ///   - `dest_file_name` (String): The name of the resulting compressed file.
-///   - `files_to_add` (`Variadic<Value>`): A variadic list of files to include in the ZIP archive.
+///   - `files_to_add` (`Variadic<Value>`): A variadic list of files to include in the archive, optionally followed by
+///     a table of options:
+///     - `level` (integer): The compression level, E.g. 0-9 for zip and gzip or 1-22 for zstd.
+///     - `base_dir` (string): The files are relative to this directory, and stored with their relative path.
+///     - `strip_prefix` (string): Prefix removed from the paths of the files to get their names in the archive.
+///     - `exclude` (string or table): Glob patterns of files not to add, matching the name or the relative path.
+///     - `follow_symlinks` (bool): Stores the files symbolic links point to. Default true, otherwise the links are
+///       stored.
+///     - `store_only` (bool): No compression in zip archives, the fastest level for the other formats.
+///     - `workers` (integer): Number of threads compressing zstd archives. Default 0, compressing in the calling
+///       thread.
+///     - `long_distance` (bool): zstd long-distance matching, better for large files with distant repetitions.
+///     - `dictionary` (string): Path of a zstd dictionary. The same dictionary is needed to decompress.
 ///
 /// # Returns
 ///
@@ -27,36 +48,79 @@ use crate::compress::zip::{create_zip_int, extract_zip_int};
 /// ```lua
 /// files.compress("archive.zip", "file1.txt", "file2.txt")
 /// files.compress("archive.tar.zst", "file1.txt", "dir1")
+/// files.compress("dump.sql.gz", "dump.sql")
+/// files.compress("site.tar.gz", "public", { base_dir = "build", exclude = { "*.map" }, level = 9 })
+/// files.compress("bundle.tar.zst", "dist", { workers = 8, long_distance = true, level = 19 })
 /// ```
 pub(crate) fn compress(_lua: &Lua, (dest_file_name, files_to_add): (String, Variadic<Value>)) -> mlua::Result<()> {
-    let mut files = vec![];
-    for arg in files_to_add.iter() {
-        files.push(PathBuf::from(arg.to_string()?));
-    }
+    let (files, options) = compress_args(files_to_add)?;
 
     let dest_file_path = PathBuf::from(&dest_file_name);
-    if dest_file_name.ends_with(".zip") {
-        create_zip_int(&dest_file_path, &files, true)?;
-    } else if dest_file_name.ends_with(".tar.zst") {
-        create_tar_zst(&dest_file_path, &files, true)?;
-    } else {
-        return Err(mlua::Error::RuntimeError("Unsupported compression algorithm. Valid extensions are: .zip and .tar.zst".to_string()));
+    match ArchiveFormat::from_name(&dest_file_name) {
+        Some(ArchiveFormat::Zip) => create_zip_int(&dest_file_path, &files, &options)?,
+        Some(ArchiveFormat::Tar(codec)) => create_tar(&dest_file_path, &files, codec, &options)?,
+        Some(ArchiveFormat::Single(codec)) => {
+            let src = match options.base_dir {
+                Some(ref base_dir) if files.len() == 1 => base_dir.join(&files[0]),
+                _ => files.first().cloned().unwrap_or_default(),
+            };
+            if files.len() != 1 || !src.is_file() {
+                return Err(mlua::Error::RuntimeError(
+                    format!("{} can only contain a single file, use a tar archive for more", dest_file_name)));
+            }
+            compress_file(&src, &dest_file_path, codec, &options)?;
+        }
+        None => return Err(unsupported_format()),
     }
     Ok(())
 }
 
+/// Splits the arguments of `files.compress` and `files.zip` into the files and the options table, if the last
+/// argument is a table.
+fn compress_args(mut args: Variadic<Value>) -> mlua::Result<(Vec<PathBuf>, CompressOptions)> {
+    let mut options = CompressOptions::default();
+    if let Some(Value::Table(tb)) = args.last() {
+        options.level = tb.get("level")?;
+        options.base_dir = tb.get::<Option<String>>("base_dir")?.map(PathBuf::from);
+        options.strip_prefix = tb.get::<Option<String>>("strip_prefix")?.map(PathBuf::from);
+        options.exclude = glob_patterns(tb.get("exclude")?)?;
+        options.follow_symlinks = tb.get::<Option<bool>>("follow_symlinks")?.unwrap_or(true);
+        options.store_only = tb.get::<Option<bool>>("store_only")?.unwrap_or(false);
+        options.workers = tb.get::<Option<u32>>("workers")?.unwrap_or(0);
+        options.long_distance = tb.get::<Option<bool>>("long_distance")?.unwrap_or(false);
+        options.dictionary = read_dictionary(tb)?;
+        args.pop();
+    }
+
+    let mut files = vec![];
+    for arg in args.iter() {
+        files.push(PathBuf::from(arg.to_string()?));
+    }
+    Ok((files, options))
+}
+
+fn unsupported_format() -> mlua::Error {
+    mlua::Error::RuntimeError(format!("Unsupported compression algorithm. Valid extensions are: {}", SUPPORTED_EXTENSIONS))
+}
+
 /// Decompresses a compressed archive into the specified output directory.
 ///
 /// This function takes the name of the compressed file and an optional output directory. If no output directory is provided,
 /// the current directory is used as the default destination.
-/// Currently, only zip and tar.zst are supported
+/// The format is detected by the content of the file, or by its extension if not recognized. A single compressed
+/// file is extracted with the name of the archive without the extension.
+///
+/// Entries with absolute paths, `..` or symbolic links pointing outside the output directory make the extraction
+/// fail.
 ///
 /// # Arguments
 ///
 /// * `_lua` - The Lua state (not used in this function).
-/// * `(src_file_name, output_dir)` - A tuple where:
+/// * `(src_file_name, output_dir, options)` - A tuple where:
 ///   - `src_file_name` (String): The name of the compressed file to extract.
 ///   - `output_dir` (`Option<String>`): An optional path to the directory where the contents will be extracted.
+///   - `options` (`Option<Table>`): Optional limits, `max_size` (total bytes extracted) and `max_entries`, and
+///     `dictionary`, the path of the zstd dictionary used to compress.
 ///
 /// # Returns
 ///
@@ -66,26 +130,131 @@ pub(crate) fn compress(_lua: &Lua, (dest_file_name, files_to_add): (String, Vari
 ///
 /// ```lua
 /// files.decompress("archive.zip", "output_directory")
-/// files.decompress("archive.tar.zst", "output_directory")
+/// files.decompress("archive.tar.zst", "output_directory", { max_size = 100 * 1024 * 1024 })
 /// ```
-pub(crate) fn decompress(_lua: &Lua, (src_file_name, output_dir): (String, Option<String>)) -> mlua::Result<()> {
+pub(crate) fn decompress(_lua: &Lua, (src_file_name, output_dir, options): (String, Option<String>, Option<Table>)) -> mlua::Result<()> {
     let output_dir = output_dir.unwrap_or(".".to_string());
     let src_file_path = PathBuf::from(&src_file_name);
     let output_dir_path = PathBuf::from(output_dir);
+    let options = extract_options(options)?;
 
-    if src_file_name.ends_with(".zip") {
-        if let Err(e) = extract_zip_int(src_file_path, output_dir_path) {
-            return Err(io::Error::new(ErrorKind::InvalidData, e.to_string()).into());
-        }
-    } else if src_file_name.ends_with(".tar.zst") {
-        extract_tar_zst(src_file_path, output_dir_path)?;
-    } else {
-        return Err(mlua::Error::RuntimeError("Unsupported compression algorithm. Valid extensions are: .zip and .tar.zst".to_string()));
+    match archive_format(&src_file_name)? {
+        ArchiveFormat::Zip => extract_zip_int(src_file_path, output_dir_path, &options).map_err(zip_error)?,
+        ArchiveFormat::Tar(codec) => extract_tar_file(src_file_path, output_dir_path, codec, &options)?,
+        ArchiveFormat::Single(codec) => decompress_file(&src_file_path, &output_dir_path, codec, &options)?,
     }
 
     Ok(())
 }
 
+/// Detects the format of an archive by its content, or by its extension if not recognized.
+fn archive_format(file_name: &str) -> mlua::Result<ArchiveFormat> {
+    ArchiveFormat::detect(Path::new(file_name))
+        .map_err(|e| mlua::Error::RuntimeError(format!("{}: {}", file_name, e)))?
+        .or(ArchiveFormat::from_name(file_name))
+        .ok_or_else(unsupported_format)
+}
+
+/// Extracts an archive, optionally only some of its entries.
+///
+/// Same as `files.decompress`, with the `only` option to select the entries to extract.
+///
+/// # Arguments
+///
+/// * `lua` - The Lua state.
+/// * `(src_file_name, output_dir, options)` - A tuple where:
+///   - `src_file_name` (String): The name of the archive to extract.
+///   - `output_dir` (`Option<String>`): An optional path to the directory where the contents will be extracted.
+///   - `options` (`Option<Table>`): The limits of `files.decompress` and `only` (string or table), glob patterns of
+///     the entries to extract. A pattern matching a directory selects all its content.
+///
+/// # Returns
+///
+/// * A `Result` indicating success or an error if the extraction fails.
+///
+/// # Example
+///
+/// ```lua
+/// files.extract("release.tar.gz", "out", { only = { "bin", "*.md" } })
+/// ```
+pub(crate) fn extract(lua: &Lua, args: (String, Option<String>, Option<Table>)) -> mlua::Result<()> {
+    decompress(lua, args)
+}
+
+/// Lists the entries of a zip or tar archive without extracting it.
+///
+/// # Arguments
+///
+/// * `lua` - The Lua state.
+/// * `src_file_name` - The name of the archive.
+///
+/// # Returns
+///
+/// * A table of entries `{ name, size, compressed_size, mode, mtime, is_dir }`. `compressed_size` is unknown (nil)
+///   for compressed tar archives, `mode` and `mtime` when the archive doesn't store them.
+///
+/// # Example
+///
+/// ```lua
+/// for _, entry in ipairs(files.list("archive.zip")) do
+///     print(entry.name, entry.size)
+/// end
+/// ```
+pub(crate) fn list(lua: &Lua, src_file_name: String) -> mlua::Result<Table> {
+    let path = PathBuf::from(&src_file_name);
+    let entries = match archive_format(&src_file_name)? {
+        ArchiveFormat::Zip => list_zip(&path).map_err(zip_error)?,
+        ArchiveFormat::Tar(codec) => list_tar(&path, codec)?,
+        ArchiveFormat::Single(_) => return Err(not_an_archive(&src_file_name)),
+    };
+
+    let res = lua.create_table()?;
+    for entry in entries {
+        let entry_tb = lua.create_table()?;
+        entry_tb.set("name", entry.name)?;
+        entry_tb.set("size", entry.size)?;
+        entry_tb.set("compressed_size", entry.compressed_size)?;
+        entry_tb.set("mode", entry.mode)?;
+        entry_tb.set("mtime", entry.mtime)?;
+        entry_tb.set("is_dir", entry.is_dir)?;
+        res.push(entry_tb)?;
+    }
+    Ok(res)
+}
+
+/// Reads the content of a file in a zip or tar archive.
+///
+/// # Arguments
+///
+/// * `lua` - The Lua state.
+/// * `(src_file_name, name)` - The name of the archive and the name of the entry, as returned by `files.list`.
+///
+/// # Returns
+///
+/// * The content of the entry, as a string. Fails if there is no such entry.
+///
+/// # Example
+///
+/// ```lua
+/// local manifest = files.read_entry("app.zip", "app/manifest.json")
+/// ```
+pub(crate) fn read_entry(lua: &Lua, (src_file_name, name): (String, String)) -> mlua::Result<mlua::String> {
+    let path = PathBuf::from(&src_file_name);
+    let content = match archive_format(&src_file_name)? {
+        ArchiveFormat::Zip => read_zip_entry(&path, &name).map_err(|e| match e {
+            ZipError::FileNotFound => mlua::Error::RuntimeError(format!("{} not found in {}", name, src_file_name)),
+            e => zip_error(e),
+        })?,
+        ArchiveFormat::Tar(codec) => read_tar_entry(&path, codec, &name)?,
+        ArchiveFormat::Single(_) => return Err(not_an_archive(&src_file_name)),
+    };
+    lua.create_string(content)
+}
+
+fn not_an_archive(file_name: &str) -> mlua::Error {
+    mlua::Error::RuntimeError(format!("{} is a single compressed file, not a zip or tar archive", file_name))
+}
+
 /// Compresses the specified files into a ZIP archive.
 ///
 /// This function takes the name of the ZIP file to create and a variadic list of file paths to add to the archive.
@@ -96,7 +265,8 @@ pub(crate) fn decompress(_lua: &Lua, (src_file_name, output_dir): (String, Optio
 /// * `_lua` - The Lua state (not used in this function).
 /// * `(zip_name, files_to_add)` - A tuple where:
 ///   - `zip_name` (String): The name of the resulting ZIP file.
-///   - `files_to_add` (`Variadic<Value>`): A variadic list of files to include in the ZIP archive.
+///   - `files_to_add` (`Variadic<Value>`): A variadic list of files to include in the ZIP archive, optionally followed
+///     by a table with the options of `files.compress`.
 ///
 /// # Returns
 ///
@@ -106,13 +276,11 @@ pub(crate) fn decompress(_lua: &Lua, (src_file_name, output_dir): (String, Optio
 ///
 /// ```lua
 /// files.zip("archive.zip", "file1.txt", "file2.txt")
+/// files.zip("logs.zip", "/var/log/app", { exclude = "*.gz", store_only = true })
 /// ```
 pub(crate) fn create_zip(_lua: &Lua, (zip_name, files_to_add): (String, Variadic<Value>)) -> mlua::Result<()> {
-    let mut files = vec![];
-    for arg in files_to_add.iter() {
-        files.push(PathBuf::from(arg.to_string()?));
-    }
-    create_zip_int(&PathBuf::from(&zip_name), &files, true)?;
+    let (files, options) = compress_args(files_to_add)?;
+    create_zip_int(&PathBuf::from(&zip_name), &files, &options)?;
     Ok(())
 }
 
@@ -124,9 +292,10 @@ pub(crate) fn create_zip(_lua: &Lua, (zip_name, files_to_add): (String, Variadic
 /// # Arguments
 ///
 /// * `_lua` - The Lua state (not used in this function).
-/// * `(zip_name, output_dir)` - A tuple where:
+/// * `(zip_name, output_dir, options)` - A tuple where:
 ///   - `zip_name` (String): The name of the ZIP file to extract.
 ///   - `output_dir` (`Option<String>`): An optional path to the directory where the contents will be extracted.
+///   - `options` (`Option<Table>`): Optional limits, `max_size` (total bytes extracted) and `max_entries`.
 ///
 /// # Returns
 ///
@@ -137,10 +306,202 @@ pub(crate) fn create_zip(_lua: &Lua, (zip_name, files_to_add): (String, Variadic
 /// ```lua
 /// files.unzip("archive.zip", "output_directory")
 /// ```
-pub(crate) fn extract_zip(_lua: &Lua, (zip_name, output_dir): (String, Option<String>)) -> mlua::Result<()> {
+pub(crate) fn extract_zip(_lua: &Lua, (zip_name, output_dir, options): (String, Option<String>, Option<Table>)) -> mlua::Result<()> {
     let output_dir = output_dir.unwrap_or(".".to_string());
-    if let Err(e) = extract_zip_int(PathBuf::from(zip_name), PathBuf::from(output_dir)) {
-        return Err(io::Error::new(ErrorKind::InvalidData, e.to_string()).into());
-    }
+    let options = extract_options(options)?;
+    extract_zip_int(PathBuf::from(zip_name), PathBuf::from(output_dir), &options).map_err(zip_error)?;
     Ok(())
 }
+
+/// Keeps the message of I/O errors, that the zip error only describes as "i/o error".
+fn zip_error(e: ZipError) -> mlua::Error {
+    match e {
+        ZipError::Io(e) => e.into(),
+        e => io::Error::new(ErrorKind::InvalidData, e.to_string()).into(),
+    }
+}
+
+/// Reads the zstd dictionary of the `dictionary` option, a path.
+fn read_dictionary(options: &Table) -> mlua::Result<Option<Vec<u8>>> {
+    match options.get::<Option<String>>("dictionary")? {
+        Some(path) => std::fs::read(&path)
+            .map(Some)
+            .map_err(|e| mlua::Error::RuntimeError(format!("{}: {}", path, e))),
+        None => Ok(None),
+    }
+}
+
+fn extract_options(options: Option<Table>) -> mlua::Result<ExtractOptions> {
+    let mut res = ExtractOptions::default();
+    if let Some(tb) = options {
+        res.max_size = tb.get("max_size")?;
+        res.max_entries = tb.get("max_entries")?;
+        res.only = glob_patterns(tb.get("only")?)?;
+        res.dictionary = read_dictionary(&tb)?;
+        res.preserve = match tb.get::<Value>("preserve")? {
+            Value::Nil => Preserve::default(),
+            Value::Boolean(preserve) => Preserve::all(preserve),
+            Value::Table(names) => Preserve::from_names(&names.sequence_values::<String>().collect::<mlua::Result<Vec<_>>>()?)?,
+            value => Preserve::from_names(&[value.to_string()?])?,
+        };
+    }
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+    use tempfile::tempdir;
+    use crate::setup::run_script;
+
+    #[test]
+    fn test_compress_formats() {
+        let temp_dir = tempdir().unwrap();
+        let dir = temp_dir.path().display();
+
+        let script = format!(r#"
+            local dir = "{}"
+            fs.mkdir(dir .. "/src/sub")
+            fs.write_file(dir .. "/src/a.txt", "a")
+            fs.write_file(dir .. "/src/sub/b.txt", "b")
+
+            for _, ext in ipairs({{ "zip", "tar", "tar.gz", "tgz", "tar.xz", "tar.bz2", "tar.zst" }}) do
+                local archive = dir .. "/archive." .. ext
+                files.compress(archive, dir .. "/src")
+                -- Renamed, the format is detected by the content
+                fs.move(archive, dir .. "/archive-" .. ext)
+                files.decompress(dir .. "/archive-" .. ext, dir .. "/out-" .. ext)
+            end
+
+            files.compress(dir .. "/a.txt.xz", dir .. "/src/a.txt")
+            files.decompress(dir .. "/a.txt.xz", dir .. "/single")
+            assert(not pcall(files.compress, dir .. "/both.gz", dir .. "/src/a.txt", dir .. "/src/sub/b.txt"))
+            assert(not pcall(files.compress, dir .. "/src.rar", dir .. "/src"))
+        "#, dir);
+        run_script(&script, PathBuf::from("test.lua"), vec![]).unwrap();
+
+        for ext in ["zip", "tar", "tar.gz", "tgz", "tar.xz", "tar.bz2", "tar.zst"] {
+            let out = temp_dir.path().join(format!("out-{}", ext));
+            assert_eq!(fs::read_to_string(out.join("src/sub/b.txt")).unwrap(), "b", "{}", ext);
+        }
+        assert_eq!(fs::read_to_string(temp_dir.path().join("single/a.txt")).unwrap(), "a");
+    }
+
+    #[test]
+    fn test_compress_options() {
+        let temp_dir = tempdir().unwrap();
+        let dir = temp_dir.path().display();
+
+        let script = format!(r#"
+            local dir = "{}"
+            fs.mkdir(dir .. "/build/public/js")
+            fs.write_file(dir .. "/build/public/index.html", "index")
+            fs.write_file(dir .. "/build/public/js/app.js", "app")
+            fs.write_file(dir .. "/build/public/js/app.js.map", "map")
+
+            for _, ext in ipairs({{ "zip", "tar.gz" }}) do
+                local options = {{ base_dir = dir .. "/build", exclude = "*.map", level = 9 }}
+                files.compress(dir .. "/site." .. ext, "public/js", options)
+                files.decompress(dir .. "/site." .. ext, dir .. "/base-" .. ext)
+
+                options = {{ strip_prefix = dir .. "/build", store_only = true }}
+                files.compress(dir .. "/stored." .. ext, dir .. "/build/public/index.html", options)
+                files.decompress(dir .. "/stored." .. ext, dir .. "/stripped-" .. ext)
+            end
+            assert(not pcall(files.compress, dir .. "/missing.zip", dir .. "/missing"))
+        "#, dir);
+        run_script(&script, PathBuf::from("test.lua"), vec![]).unwrap();
+
+        for ext in ["zip", "tar.gz"] {
+            let out = temp_dir.path().join(format!("base-{}", ext));
+            assert_eq!(fs::read_to_string(out.join("public/js/app.js")).unwrap(), "app", "{}", ext);
+            assert!(!out.join("public/js/app.js.map").exists(), "{}", ext);
+            let out = temp_dir.path().join(format!("stripped-{}", ext));
+            assert_eq!(fs::read_to_string(out.join("public/index.html")).unwrap(), "index", "{}", ext);
+        }
+    }
+
+    #[test]
+    fn test_list_and_extract() {
+        let temp_dir = tempdir().unwrap();
+        let dir = temp_dir.path().display();
+
+        let script = format!(r#"
+            local dir = "{}"
+            fs.mkdir(dir .. "/app/bin")
+            fs.write_file(dir .. "/app/bin/run.sh", "run")
+            fs.write_file(dir .. "/app/README.md", "readme")
+
+            for _, ext in ipairs({{ "zip", "tar.gz" }}) do
+                local archive = dir .. "/app." .. ext
+                files.compress(archive, dir .. "/app")
+
+                local entries = files.list(archive)
+                local by_name = {{}}
+                for _, entry in ipairs(entries) do
+                    by_name[entry.name:gsub("/$", "")] = entry
+                end
+                assert(by_name["app/bin"].is_dir, ext)
+                assert(by_name["app/README.md"].size == 6, ext)
+                assert(not by_name["app/README.md"].is_dir, ext)
+                assert(by_name["app/README.md"].mtime ~= nil, ext)
+
+                assert(files.read_entry(archive, "app/bin/run.sh") == "run", ext)
+                assert(not pcall(files.read_entry, archive, "app/missing.txt"), ext)
+
+                files.extract(archive, dir .. "/out-" .. ext, {{ only = "app/bin" }})
+            end
+            assert(files.list(dir .. "/app.zip")[1].compressed_size ~= nil)
+        "#, dir);
+        run_script(&script, PathBuf::from("test.lua"), vec![]).unwrap();
+
+        for ext in ["zip", "tar.gz"] {
+            let out = temp_dir.path().join(format!("out-{}", ext));
+            assert_eq!(fs::read_to_string(out.join("app/bin/run.sh")).unwrap(), "run", "{}", ext);
+            assert!(!out.join("app/README.md").exists(), "{}", ext);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_preserve_metadata() {
+        use std::os::unix::fs::PermissionsExt;
+        use filetime::FileTime;
+
+        let temp_dir = tempdir().unwrap();
+        let src = temp_dir.path().join("app");
+        fs::create_dir_all(src.join("bin")).unwrap();
+        fs::write(src.join("bin/run"), "#!/bin/sh").unwrap();
+        fs::set_permissions(src.join("bin/run"), fs::Permissions::from_mode(0o750)).unwrap();
+        std::os::unix::fs::symlink("bin/run", src.join("run")).unwrap();
+        let mtime = FileTime::from_unix_time(1_600_000_000, 0);
+        filetime::set_file_mtime(src.join("bin/run"), mtime).unwrap();
+        filetime::set_file_mtime(src.join("bin"), mtime).unwrap();
+
+        let script = format!(r#"
+            local dir = "{}"
+            for _, ext in ipairs({{ "zip", "tar.zst" }}) do
+                files.compress(dir .. "/app." .. ext, dir .. "/app", {{ follow_symlinks = false }})
+                files.decompress(dir .. "/app." .. ext, dir .. "/out-" .. ext)
+                files.decompress(dir .. "/app." .. ext, dir .. "/plain-" .. ext, {{ preserve = false }})
+                assert(not pcall(files.decompress, dir .. "/app." .. ext, dir .. "/x", {{ preserve = {{ "acl" }} }}))
+            end
+        "#, temp_dir.path().display());
+        run_script(&script, PathBuf::from("test.lua"), vec![]).unwrap();
+
+        for ext in ["zip", "tar.zst"] {
+            let out = temp_dir.path().join(format!("out-{}/app", ext));
+            assert_eq!(fs::read_link(out.join("run")).unwrap(), PathBuf::from("bin/run"), "{}", ext);
+            let md = fs::metadata(out.join("bin/run")).unwrap();
+            assert_eq!(md.permissions().mode() & 0o777, 0o750, "{}", ext);
+            assert_eq!(FileTime::from_last_modification_time(&md), mtime, "{}", ext);
+            let md = fs::metadata(out.join("bin")).unwrap();
+            assert_eq!(FileTime::from_last_modification_time(&md), mtime, "{}", ext);
+
+            let plain = temp_dir.path().join(format!("plain-{}/app", ext));
+            let md = fs::metadata(plain.join("bin/run")).unwrap();
+            assert_ne!(FileTime::from_last_modification_time(&md), mtime, "{}", ext);
+        }
+    }
+}
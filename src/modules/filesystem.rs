@@ -1,51 +1,167 @@
 use std::{fs, io};
-use std::io::ErrorKind;
+use std::io::{ErrorKind, Write};
 use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use filetime::{set_file_mtime, set_file_times, FileTime};
 use mlua::{Lua, Table, Value, Variadic};
+use walkdir::{DirEntry, WalkDir};
+use crate::utils::atomic_write;
 
-/// Lists the contents of the specified directory or the current directory if no path is provided.
+/// Lists the contents of the specified directories or the current directory if no path is provided.
 ///
-/// If a path is provided, it lists the files in that directory. Otherwise, it lists the contents
-/// of the current working directory.
+/// Symbolic links are listed as entries, they are not followed.
 ///
 /// # Arguments
 ///
-/// * `_lua` - The Lua state (not used in this function).
-/// * `paths` - A variadic list of directory paths to list. Only the first path is used.
+/// * `lua` - The Lua state.
+/// * `args` - A variadic list of directory paths to list, optionally followed by a table of options:
+///   - `long` (bool): Returns tables `{ name, path, type, size, mtime }` instead of paths. Default false.
+///   - `all` (bool): Include entries starting with `.`. Default true.
+///   - `sort` (string): Sort by `name` (default), `mtime`, `size` or `type`. `none` keeps the directory order.
+///   - `reverse` (bool): Reverse the sort order. Default false.
+///   - `filter` (string): A glob matched against the entry name.
 ///
 /// # Returns
 ///
-/// * A vector of strings containing the file paths within the directory.
+/// * A table with the file paths (or entry tables in long mode) and, if some entries could not be read, a table
+///   with the error messages. Otherwise the second value is `nil`.
 ///
 /// # Errors
 ///
-/// * Returns an error if the directory cannot be read.
+/// * Returns an error if a directory cannot be read.
 ///
 /// # Example (in Lua)
 ///
 /// ```lua
 /// local files = fs.ls("/some/directory")
-/// print(files)
+/// local logs, errors = fs.ls("/var/log", { long = true, sort = "mtime", reverse = true, filter = "*.log" })
+/// print(logs[1].name, logs[1].size)
 /// ```
-pub(crate) fn ls(_lua: &Lua, paths: Variadic<Value>) -> mlua::Result<Vec<String>> {
-    let mut src_path = if paths.is_empty() {
-        vec![PathBuf::from(".")]
-    } else {
-        let mut res = vec![];
-        for path in paths.iter() {
-            res.push(PathBuf::from(path.to_string()?));
+pub(crate) fn ls(lua: &Lua, args: Variadic<Value>) -> mlua::Result<(Table, Option<Vec<String>>)> {
+    let mut paths = vec![];
+    let mut options = LsOptions::default();
+    for arg in args.iter() {
+        match arg {
+            Value::Table(tb) => options = LsOptions::from_table(tb)?,
+            Value::Nil => {}
+            value => paths.push(PathBuf::from(value.to_string()?)),
         }
-        res
-    };
+    }
+    if paths.is_empty() {
+        paths.push(PathBuf::from("."));
+    }
 
-    // TODO: Read more than one
-    let src_path = src_path.pop().unwrap();
-    let files: Vec<String> = fs::read_dir(&src_path)?
-        .map(|f| f.unwrap().path())
-        .map(|f| f.to_str().unwrap().to_string())
-        .collect();
+    let mut entries = vec![];
+    let mut errors = vec![];
+    for path in paths {
+        for dir_entry in fs::read_dir(&path)? {
+            let dir_entry = match dir_entry {
+                Ok(e) => e,
+                Err(e) => {
+                    errors.push(format!("{}: {}", path.display(), e));
+                    continue;
+                }
+            };
 
-    Ok(files)
+            let name = dir_entry.file_name().to_string_lossy().to_string();
+            if !options.all && name.starts_with('.') {
+                continue;
+            }
+            if let Some(ref pattern) = options.filter && !pattern.matches(&name) {
+                continue;
+            }
+
+            // DirEntry::metadata does not follow symbolic links
+            match dir_entry.metadata() {
+                Ok(md) => entries.push(LsEntry {
+                    name,
+                    path: dir_entry.path().to_string_lossy().to_string(),
+                    file_type: file_type_name(md.file_type()),
+                    size: md.len(),
+                    mtime: md.modified().ok().map(system_time_secs),
+                }),
+                Err(e) => errors.push(format!("{}: {}", dir_entry.path().display(), e)),
+            }
+        }
+    }
+
+    options.sort_entries(&mut entries);
+
+    let res = lua.create_table()?;
+    for entry in entries {
+        if options.long {
+            let entry_tb = lua.create_table()?;
+            entry_tb.set("name", entry.name)?;
+            entry_tb.set("path", entry.path)?;
+            entry_tb.set("type", entry.file_type)?;
+            entry_tb.set("size", entry.size)?;
+            entry_tb.set("mtime", entry.mtime)?;
+            res.push(entry_tb)?;
+        } else {
+            res.push(entry.path)?;
+        }
+    }
+
+    let errors = if errors.is_empty() { None } else { Some(errors) };
+    Ok((res, errors))
+}
+
+struct LsEntry {
+    name: String,
+    path: String,
+    file_type: &'static str,
+    size: u64,
+    mtime: Option<i64>,
+}
+
+struct LsOptions {
+    long: bool,
+    all: bool,
+    sort: String,
+    reverse: bool,
+    filter: Option<glob::Pattern>,
+}
+
+impl Default for LsOptions {
+    fn default() -> Self {
+        Self { long: false, all: true, sort: "name".to_string(), reverse: false, filter: None }
+    }
+}
+
+impl LsOptions {
+    fn from_table(tb: &Table) -> mlua::Result<Self> {
+        let default = Self::default();
+        let filter = match tb.get::<Option<String>>("filter")? {
+            Some(f) => Some(glob::Pattern::new(&f)
+                .map_err(|e| mlua::Error::RuntimeError(format!("Invalid glob pattern {}: {}", f, e)))?),
+            None => None,
+        };
+        let sort = tb.get::<Option<String>>("sort")?.unwrap_or(default.sort);
+        if !["name", "mtime", "size", "type", "none"].contains(&sort.as_str()) {
+            return Err(mlua::Error::RuntimeError(format!("Invalid sort option: {}", sort)));
+        }
+
+        Ok(Self {
+            long: tb.get::<Option<bool>>("long")?.unwrap_or(default.long),
+            all: tb.get::<Option<bool>>("all")?.unwrap_or(default.all),
+            sort,
+            reverse: tb.get::<Option<bool>>("reverse")?.unwrap_or(default.reverse),
+            filter,
+        })
+    }
+
+    fn sort_entries(&self, entries: &mut [LsEntry]) {
+        match self.sort.as_str() {
+            "mtime" => entries.sort_by(|a, b| a.mtime.cmp(&b.mtime).then_with(|| a.name.cmp(&b.name))),
+            "size" => entries.sort_by(|a, b| a.size.cmp(&b.size).then_with(|| a.name.cmp(&b.name))),
+            "type" => entries.sort_by(|a, b| a.file_type.cmp(b.file_type).then_with(|| a.name.cmp(&b.name))),
+            "name" => entries.sort_by(|a, b| a.name.cmp(&b.name)),
+            _ => {}
+        }
+        if self.reverse {
+            entries.reverse();
+        }
+    }
 }
 
 /// Creates a directory at the specified path.
@@ -96,7 +212,8 @@ pub(crate) fn mkdir(_lua: &Lua, path: String) -> mlua::Result<()> {
 /// fs.rmdir("/some/directory", { recursive = true })
 /// ```
 pub(crate) fn rmdir(_lua: &Lua, (path, options): (String, Option<Table>)) -> mlua::Result<bool> {
-    let md = match fs::metadata(&path) {
+    // A symbolic link to a directory is not a directory, it must be removed with fs.rm
+    let md = match fs::symlink_metadata(&path) {
         Ok(x) => x,
         Err(_) => return Ok(false),
     };
@@ -122,7 +239,7 @@ pub(crate) fn rmdir(_lua: &Lua, (path, options): (String, Option<Table>)) -> mlu
     Ok(res.is_ok())
 }
 
-/// Copies a file from the source path to the target path.
+/// Copies a file or a directory tree from the source path to the target path.
 ///
 /// If the target path is a directory, the file is copied into the directory with its original name.
 ///
@@ -131,43 +248,397 @@ pub(crate) fn rmdir(_lua: &Lua, (path, options): (String, Option<Table>)) -> mlu
 /// * `_lua` - The Lua state (not used in this function).
 /// * `src` - The source file path.
 /// * `target` - The target file or directory path.
+/// * `options` - Optional table containing:
+///   - `recursive` (bool): Copy directories and their contents. Default false.
+///   - `overwrite` (bool): Overwrite existing files. Default true.
+///   - `preserve` (bool): Preserve modification and access times. Default false.
+///   - `exclude` (string or table): Glob patterns of files to skip, matched against the name and the path
+///     relative to `src`.
 ///
 /// # Returns
 ///
-/// * `Ok(())` if the file is successfully copied.
+/// * The number of files copied.
 /// * Returns an error if the source file does not exist or the copy operation fails.
 ///
 /// # Example (in Lua)
 ///
 /// ```lua
 /// fs.copy("/path/to/source", "/path/to/destination")
+/// fs.copy("src", "/tmp/backup", { recursive = true, exclude = { "*.o", "target" } })
 /// ```
-pub(crate) fn copy_file(_lua: &Lua, (src, target): (String, String)) -> mlua::Result<()> {
+pub(crate) fn copy_file(_lua: &Lua, (src, target, options): (String, String, Option<Table>)) -> mlua::Result<u64> {
     let src_path = PathBuf::from(&src);
     if !src_path.exists() {
         return Err(mlua::Error::RuntimeError(format!("Invalid source path {}", src)));
     }
 
+    let mut opts = CopyOptions::default();
+    if let Some(ref tb) = options {
+        opts.recursive = tb.get::<Option<bool>>("recursive")?.unwrap_or(false);
+        opts.overwrite = tb.get::<Option<bool>>("overwrite")?.unwrap_or(true);
+        opts.preserve = tb.get::<Option<bool>>("preserve")?.unwrap_or(false);
+        opts.exclude = glob_patterns(tb.get("exclude")?)?;
+    }
+
     let mut target_path = PathBuf::from(&target);
     if target_path.is_dir() {
-        let fname = src_path.file_name().unwrap();
-        target_path = target_path.join(fname);
+        target_path = target_path.join(file_name(&src_path)?);
+    }
+
+    let copied = if src_path.is_dir() {
+        if !opts.recursive {
+            return Err(io::Error::new(ErrorKind::InvalidInput, format!("{} is a directory. Use recursive = true", src)).into());
+        }
+        if resolve_path(&target_path)?.starts_with(src_path.canonicalize()?) {
+            return Err(io::Error::new(ErrorKind::InvalidInput,
+                format!("Cannot copy {} into itself ({})", src, target_path.display())).into());
+        }
+        copy_tree(&src_path, &target_path, &opts)?
+    } else {
+        copy_single_file(&src_path, &target_path, &opts)?;
+        1
+    };
+
+    Ok(copied)
+}
+
+/// Returns the file name of a path, resolving it for paths like `.` or `..`.
+fn file_name(path: &Path) -> io::Result<PathBuf> {
+    match path.file_name() {
+        Some(name) => Ok(PathBuf::from(name)),
+        None => path.canonicalize()?.file_name().map(PathBuf::from).ok_or_else(|| io::Error::new(
+            ErrorKind::InvalidInput, format!("{} has no file name", path.display()))),
+    }
+}
+
+/// Returns the absolute path of a path that may not exist yet, resolving the symbolic links of its existing
+/// ancestors.
+fn resolve_path(path: &Path) -> io::Result<PathBuf> {
+    let path = std::path::absolute(path)?;
+    let mut missing = vec![];
+    let mut ancestor = path.as_path();
+    loop {
+        if let Ok(resolved) = ancestor.canonicalize() {
+            return Ok(missing.iter().rev().fold(resolved, |p, name| p.join(name)));
+        }
+        match (ancestor.parent(), ancestor.file_name()) {
+            (Some(parent), Some(name)) => {
+                missing.push(name);
+                ancestor = parent;
+            }
+            _ => return Ok(path.clone()),
+        }
+    }
+}
+
+/// Synchronises the contents of the source directory into the target directory, like `rsync -a src/ target/`.
+///
+/// Only new or changed files are copied. A file is considered changed if its size or modification time differ, or,
+/// if `checksum` is set, if its content differ. Modification times are always preserved.
+///
+/// # Arguments
+///
+/// * `lua` - The Lua state.
+/// * `src` - The source directory.
+/// * `target` - The target directory. Created if it does not exist.
+/// * `options` - Optional table containing:
+///   - `delete` (bool): Delete files in target that are not in source. Default false.
+///   - `dry_run` (bool): Only report what would be done. Default false.
+///   - `checksum` (bool): Compare file contents instead of modification times. Default false.
+///   - `exclude` (string or table): Glob patterns of files to skip.
+///
+/// # Returns
+///
+/// * A table `{ copied, updated, deleted, unchanged }`, where the first three are lists of paths relative to the
+///   directories and unchanged is the number of files not copied.
+///
+/// # Example (in Lua)
+///
+/// ```lua
+/// local res = fs.sync("site", "/var/www/site", { delete = true, dry_run = true })
+/// for _, f in ipairs(res.deleted) do print("would delete " .. f) end
+/// ```
+pub(crate) fn sync(lua: &Lua, (src, target, options): (String, String, Option<Table>)) -> mlua::Result<Table> {
+    let src_path = PathBuf::from(&src);
+    let target_path = PathBuf::from(&target);
+    if !src_path.is_dir() {
+        return Err(io::Error::new(ErrorKind::InvalidInput, format!("{} is not a directory", src)).into());
+    }
+
+    let mut delete = false;
+    let mut dry_run = false;
+    let mut checksum = false;
+    let mut exclude = vec![];
+    if let Some(ref tb) = options {
+        delete = tb.get::<Option<bool>>("delete")?.unwrap_or(false);
+        dry_run = tb.get::<Option<bool>>("dry_run")?.unwrap_or(false);
+        checksum = tb.get::<Option<bool>>("checksum")?.unwrap_or(false);
+        exclude = glob_patterns(tb.get("exclude")?)?;
+    }
+    let opts = CopyOptions { recursive: true, overwrite: true, preserve: true, exclude };
+
+    let copied = lua.create_table()?;
+    let updated = lua.create_table()?;
+    let deleted = lua.create_table()?;
+    let mut unchanged = 0;
+
+    if !dry_run {
+        fs::create_dir_all(&target_path)?;
     }
 
-    fs::copy(src_path, target_path)?;
+    let walker = WalkDir::new(&src_path).min_depth(1).into_iter()
+        .filter_entry(|e| !is_excluded(&src_path, e.path(), &opts.exclude));
+    for entry in walker {
+        let entry = entry.map_err(io::Error::from)?;
+        let rel_path = entry.path().strip_prefix(&src_path).unwrap();
+        let dst = target_path.join(rel_path);
+        let rel_name = rel_path.to_string_lossy();
+
+        if entry.file_type().is_dir() {
+            if !dry_run {
+                fs::create_dir_all(&dst)?;
+            }
+            continue;
+        }
+
+        let dst_exists = fs::symlink_metadata(&dst).is_ok();
+        if dst_exists && !file_changed(entry.path(), &dst, checksum)? {
+            unchanged += 1;
+            continue;
+        }
+
+        if dst_exists {
+            updated.push(rel_name)?;
+        } else {
+            copied.push(rel_name)?;
+        }
+        if !dry_run {
+            copy_single_file(entry.path(), &dst, &opts)?;
+        }
+    }
+
+    if delete && target_path.is_dir() {
+        let walker = WalkDir::new(&target_path).min_depth(1).into_iter()
+            .filter_entry(|e| !is_excluded(&target_path, e.path(), &opts.exclude));
+        for entry in walker {
+            let entry = entry.map_err(io::Error::from)?;
+            let rel_path = entry.path().strip_prefix(&target_path).unwrap();
+            if fs::symlink_metadata(src_path.join(rel_path)).is_ok() {
+                continue;
+            }
+            // The parent directory may be already deleted
+            if !dry_run && fs::symlink_metadata(entry.path()).is_err() {
+                continue;
+            }
+
+            deleted.push(rel_path.to_string_lossy())?;
+            if !dry_run {
+                if entry.file_type().is_dir() {
+                    fs::remove_dir_all(entry.path())?;
+                } else {
+                    fs::remove_file(entry.path())?;
+                }
+            }
+        }
+    }
+
+    let res = lua.create_table()?;
+    res.set("copied", copied)?;
+    res.set("updated", updated)?;
+    res.set("deleted", deleted)?;
+    res.set("unchanged", unchanged)?;
+    Ok(res)
+}
+
+struct CopyOptions {
+    recursive: bool,
+    overwrite: bool,
+    preserve: bool,
+    exclude: Vec<glob::Pattern>,
+}
+
+impl Default for CopyOptions {
+    fn default() -> Self {
+        Self {
+            recursive: false,
+            overwrite: true,
+            preserve: false,
+            exclude: vec![],
+        }
+    }
+}
 
+fn copy_tree(src: &Path, target: &Path, opts: &CopyOptions) -> io::Result<u64> {
+    let mut copied = 0;
+    fs::create_dir_all(target)?;
+
+    let mut dirs = vec![(src.to_path_buf(), target.to_path_buf())];
+    let walker = WalkDir::new(src).min_depth(1).into_iter()
+        .filter_entry(|e| !is_excluded(src, e.path(), &opts.exclude));
+    for entry in walker {
+        let entry = entry?;
+        let dst = target.join(entry.path().strip_prefix(src).unwrap());
+        if entry.file_type().is_dir() {
+            fs::create_dir_all(&dst)?;
+            dirs.push((entry.path().to_path_buf(), dst));
+        } else {
+            copy_single_file(entry.path(), &dst, opts)?;
+            copied += 1;
+        }
+    }
+
+    // Directories are updated after their contents, otherwise creating the files changes their mtime
+    if opts.preserve {
+        for (src_dir, dst_dir) in dirs.iter().rev() {
+            fs::set_permissions(dst_dir, fs::metadata(src_dir)?.permissions())?;
+            copy_times(src_dir, dst_dir)?;
+        }
+    }
+
+    Ok(copied)
+}
+
+/// Copies a file, or recreates a symbolic link with the same target, replacing an existing target unless
+/// `overwrite` is false. The permissions are copied, and the times with `preserve`.
+fn copy_single_file(src: &Path, target: &Path, opts: &CopyOptions) -> io::Result<()> {
+    let target_md = fs::symlink_metadata(target);
+    if target_md.is_ok() {
+        if !opts.overwrite {
+            return Err(io::Error::new(ErrorKind::AlreadyExists, format!("{} already exists", target.display())));
+        }
+        // Symbolic links are replaced, not followed
+        if target_md?.file_type().is_symlink() {
+            fs::remove_file(target)?;
+        }
+    }
+
+    let src_md = fs::symlink_metadata(src)?;
+    #[cfg(unix)]
+    if src_md.file_type().is_symlink() {
+        std::os::unix::fs::symlink(fs::read_link(src)?, target)?;
+        return Ok(());
+    }
+
+    // Permissions are copied by fs::copy
+    fs::copy(src, target)?;
+    if opts.preserve {
+        copy_times(src, target)?;
+    }
     Ok(())
 }
 
-/// Moves a file from the source path to the target path.
+fn copy_times(src: &Path, target: &Path) -> io::Result<()> {
+    let md = fs::metadata(src)?;
+    set_file_times(target, FileTime::from_last_access_time(&md), FileTime::from_last_modification_time(&md))
+}
+
+/// Checks if a file of `fs.sync` must be copied again. Symbolic links are compared by their target, as they are
+/// recreated without their modification time.
+fn file_changed(src: &Path, target: &Path, checksum: bool) -> io::Result<bool> {
+    let src_md = fs::symlink_metadata(src)?;
+    let target_md = fs::symlink_metadata(target)?;
+    if src_md.file_type() != target_md.file_type() {
+        return Ok(true);
+    }
+    if src_md.file_type().is_symlink() {
+        return Ok(fs::read_link(src)? != fs::read_link(target)?);
+    }
+    if src_md.len() != target_md.len() {
+        return Ok(true);
+    }
+
+    if checksum {
+        return Ok(!same_content(src, target)?);
+    }
+
+    let src_mtime = FileTime::from_last_modification_time(&src_md);
+    let target_mtime = FileTime::from_last_modification_time(&target_md);
+    Ok(src_mtime.unix_seconds() != target_mtime.unix_seconds())
+}
+
+/// Checks if two files have the same content, comparing them byte by byte.
 ///
-/// If the target path is a directory, the file is moved into the directory with its original name.
+/// # Example (in Lua)
+///
+/// ```lua
+/// if not fs.same("config.toml", "/etc/app/config.toml") then
+///     fs.copy("config.toml", "/etc/app/config.toml")
+/// end
+/// ```
+pub(crate) fn same(_lua: &Lua, (a, b): (String, String)) -> mlua::Result<bool> {
+    if fs::metadata(&a)?.len() != fs::metadata(&b)?.len() {
+        return Ok(false);
+    }
+    Ok(same_content(Path::new(&a), Path::new(&b))?)
+}
+
+fn same_content(a: &Path, b: &Path) -> io::Result<bool> {
+    let mut reader_a = io::BufReader::new(fs::File::open(a)?);
+    let mut reader_b = io::BufReader::new(fs::File::open(b)?);
+    let mut buf_a = [0u8; 8192];
+    let mut buf_b = [0u8; 8192];
+    loop {
+        let n = read_full(&mut reader_a, &mut buf_a)?;
+        let m = read_full(&mut reader_b, &mut buf_b)?;
+        if n != m || buf_a[..n] != buf_b[..m] {
+            return Ok(false);
+        }
+        if n == 0 {
+            return Ok(true);
+        }
+    }
+}
+
+fn read_full<R: io::Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut total = 0;
+    while total < buf.len() {
+        match reader.read(&mut buf[total..])? {
+            0 => break,
+            n => total += n,
+        }
+    }
+    Ok(total)
+}
+
+/// Converts a string or a table of strings into glob patterns.
+pub(crate) fn glob_patterns(value: Value) -> mlua::Result<Vec<glob::Pattern>> {
+    let patterns: Vec<String> = match value {
+        Value::Nil => vec![],
+        Value::String(s) => vec![s.to_str()?.to_string()],
+        Value::Table(t) => t.sequence_values::<String>().collect::<mlua::Result<_>>()?,
+        _ => return Err(mlua::Error::RuntimeError("Patterns must be a string or a table of strings".to_string())),
+    };
+
+    patterns.iter()
+        .map(|p| glob::Pattern::new(p)
+            .map_err(|e| mlua::Error::RuntimeError(format!("Invalid glob pattern {}: {}", p, e))))
+        .collect()
+}
+
+/// Checks if a path matches any of the patterns, either by its name or by its path relative to `root`.
+pub(crate) fn is_excluded(root: &Path, path: &Path, patterns: &[glob::Pattern]) -> bool {
+    if patterns.is_empty() {
+        return false;
+    }
+    let rel_path = path.strip_prefix(root).unwrap_or(path);
+    let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    patterns.iter().any(|p| p.matches(&name) || p.matches_path(rel_path))
+}
+
+/// Moves a file or a directory from the source path to the target path.
+///
+/// If the target path is a directory, the source is moved into the directory with its original name.
+/// When source and target are in different filesystems, the source is copied, preserving permissions and
+/// timestamps, and then deleted. An existing target is restored if the move fails.
 ///
 /// # Arguments
 ///
 /// * `_lua` - The Lua state (not used in this function).
 /// * `src` - The source file path.
 /// * `target` - The target file or directory path.
+/// * `options` - Optional table containing:
+///   - `overwrite` (bool): If `false`, fails when the target already exists. Default true.
+///   - `replace_dir` (bool): Replaces a target directory that is not empty, deleting its contents. Default false.
 ///
 /// # Returns
 ///
@@ -178,21 +649,93 @@ pub(crate) fn copy_file(_lua: &Lua, (src, target): (String, String)) -> mlua::Re
 ///
 /// ```lua
 /// fs.move("/path/to/source", "/path/to/destination")
+/// fs.move(os.mkdtemp() .. "/build", "/mnt/artifacts", { overwrite = false })
 /// ```
-pub(crate) fn move_file(_lua: &Lua, (src, target): (String, String)) -> mlua::Result<()> {
+pub(crate) fn move_file(_lua: &Lua, (src, target, options): (String, String, Option<Table>)) -> mlua::Result<()> {
     let src_path = PathBuf::from(&src);
-    if !src_path.exists() {
-        return Err(mlua::Error::RuntimeError(format!("Invalid source path {}", src)));
+    let src_md = fs::symlink_metadata(&src_path)
+        .map_err(|_| mlua::Error::RuntimeError(format!("Invalid source path {}", src)))?;
+
+    let mut overwrite = true;
+    let mut replace_dir = false;
+    if let Some(ref tb) = options {
+        overwrite = tb.get::<Option<bool>>("overwrite")?.unwrap_or(true);
+        replace_dir = tb.get::<Option<bool>>("replace_dir")?.unwrap_or(false);
     }
 
     let mut target_path = PathBuf::from(&target);
     if target_path.is_dir() {
-        let fname = src_path.file_name().unwrap();
-        target_path = target_path.join(fname);
+        target_path = target_path.join(file_name(&src_path)?);
     }
 
-    fs::rename(src_path, target_path)?;
+    // A symbolic link is moved, not the directory it points to
+    let abs_src = std::path::absolute(&src_path)?;
+    let resolved_src = match abs_src.parent() {
+        Some(parent) if src_md.file_type().is_symlink() => resolve_path(parent)?.join(file_name(&src_path)?),
+        _ => src_path.canonicalize()?,
+    };
+    if resolve_path(&target_path)?.starts_with(&resolved_src) {
+        return Err(io::Error::new(ErrorKind::InvalidInput,
+            format!("Cannot move {} into itself ({})", src, target_path.display())).into());
+    }
 
+    // The existing target is put aside until the source is in place, and restored if the move fails
+    let mut backup = None;
+    if let Ok(target_md) = fs::symlink_metadata(&target_path) {
+        if !overwrite {
+            return Err(io::Error::new(ErrorKind::AlreadyExists, format!("{} already exists", target_path.display())).into());
+        }
+        // As rename, a directory with contents is only replaced on request
+        if target_md.is_dir() && !replace_dir && fs::read_dir(&target_path)?.next().is_some() {
+            return Err(io::Error::new(ErrorKind::DirectoryNotEmpty, format!("{} is a directory that is not empty. \
+                Use replace_dir = true to replace it", target_path.display())).into());
+        }
+        let parent = match target_path.parent() {
+            Some(p) if !p.as_os_str().is_empty() => p,
+            _ => Path::new("."),
+        };
+        let backup_dir = tempfile::Builder::new().prefix(".lush-move").tempdir_in(parent)?;
+        let backup_path = backup_dir.path().join("target");
+        fs::rename(&target_path, &backup_path)?;
+        backup = Some((backup_dir, backup_path));
+    }
+
+    let mut copied = false;
+    let res = match fs::rename(&src_path, &target_path) {
+        Err(e) if e.kind() == ErrorKind::CrossesDevices => {
+            copied = true;
+            copy_for_move(&src_path, &target_path)
+        }
+        res => res,
+    };
+    if let Err(e) = res {
+        // Removes what was copied before the failure
+        if copied {
+            delete_recursively(&target_path, true)?;
+        }
+        if let Some((_backup_dir, backup_path)) = backup {
+            fs::rename(&backup_path, &target_path)?;
+        }
+        return Err(e.into());
+    }
+
+    // The backup directory is deleted when dropped
+    drop(backup);
+    if copied {
+        delete_recursively(&src_path, true)?;
+    }
+    Ok(())
+}
+
+/// Copies a file or a directory tree to move it to another filesystem, preserving permissions and timestamps.
+fn copy_for_move(src: &Path, target: &Path) -> io::Result<()> {
+    let opts = CopyOptions { recursive: true, overwrite: true, preserve: true, exclude: vec![] };
+    let md = fs::symlink_metadata(src)?;
+    if md.is_dir() {
+        copy_tree(src, target, &opts)?;
+    } else {
+        copy_single_file(src, target, &opts)?;
+    }
     Ok(())
 }
 
@@ -258,41 +801,956 @@ pub(crate) fn is_dir(_lua: &Lua, path: String) -> mlua::Result<bool> {
 
 pub(crate) fn is_file(_lua: &Lua, path: String) -> mlua::Result<bool> {
     let path = PathBuf::from(&path);
-    Ok(path.is_dir())
+    Ok(path.is_file())
 }
 
-pub(crate) fn parent(_lua: &Lua, path: String) -> mlua::Result<Option<String>> {
-    let path = PathBuf::from(&path).parent()
-        .map(|x| x.to_str().unwrap().to_string());
-    Ok(path)
+/// Same as `path.dirname`.
+pub(crate) fn parent(lua: &Lua, path: String) -> mlua::Result<Option<String>> {
+    crate::modules::path::dirname(lua, path)
 }
 
+/// Deletes a file or a directory. Symbolic links are removed, never followed, so deleting a link to a directory
+/// does not delete the directory contents.
 fn delete_recursively<P: AsRef<Path>>(path: P, recursive: bool) -> io::Result<bool> {
     let path = path.as_ref();
-    if path.is_dir() {
+    let md = match fs::symlink_metadata(path) {
+        Ok(md) => md,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(e),
+    };
+
+    if md.file_type().is_symlink() {
+        remove_symlink(path)?;
+    } else if md.is_dir() {
         if recursive {
+            // remove_dir_all does not follow the symbolic links inside the directory
             fs::remove_dir_all(path)?;
         } else {
             return Err(io::Error::new(ErrorKind::InvalidInput, "path is a directory"));
         }
-    } else if path.is_file() {
-        fs::remove_file(path)?;
     } else {
-        return Ok(false);
+        fs::remove_file(path)?;
     }
     Ok(true)
 }
 
-pub(crate) fn read_file(lua: &Lua, path: String) -> mlua::Result<Value> {
-    let s: Value = match fs::read_to_string(path) {
-        Ok(s) => Value::String(lua.create_string(s.as_str())?),
-        Err(_) => mlua::Value::Nil,
+#[cfg(unix)]
+fn remove_symlink(path: &Path) -> io::Result<()> {
+    fs::remove_file(path)
+}
+
+#[cfg(windows)]
+fn remove_symlink(path: &Path) -> io::Result<()> {
+    // Directory symbolic links are removed as directories on Windows
+    if path.is_dir() {
+        fs::remove_dir(path)
+    } else {
+        fs::remove_file(path)
+    }
+}
+
+/// Reads a file and returns its content as string.
+///
+/// # Returns
+///
+/// * The content of the file, or `nil` and the error message if the file cannot be read.
+///
+/// # Example (in Lua)
+///
+/// ```lua
+/// local content, err = fs.read_file("/tmp/my-file.txt")
+/// ```
+pub(crate) fn read_file(lua: &Lua, path: String) -> mlua::Result<(Value, Option<String>)> {
+    let res = match fs::read(&path) {
+        Ok(s) => (Value::String(lua.create_string(s)?), None),
+        Err(e) => (Value::Nil, Some(format!("{}: {}", path, e))),
     };
 
-    Ok(s)
+    Ok(res)
 }
 
-pub(crate) fn write_file(_lua: &Lua, (path, value): (String, String)) -> mlua::Result<bool> {
-    let res = fs::write(path, value);
-    Ok(res.is_ok())
-}
\ No newline at end of file
+/// Writes a string to a file, replacing its content.
+///
+/// With the `atomic` option, the content is written to a temporary file and renamed over the target, so the file
+/// is never left truncated if the script is interrupted.
+///
+/// # Returns
+///
+/// * `true`, or `false` and the error message if the file cannot be written.
+///
+/// # Example (in Lua)
+///
+/// ```lua
+/// local ok, err = fs.write_file("/tmp/my-file.txt", "content")
+/// fs.write_file("/etc/my-app.conf", "content", { atomic = true })
+/// ```
+pub(crate) fn write_file(_lua: &Lua, (path, value, options): (String, mlua::String, Option<Table>)) -> mlua::Result<(bool, Option<String>)> {
+    let atomic = atomic_option(&options)?;
+    let res = atomic_write::write_file(&path, &value.as_bytes(), atomic);
+    Ok(bool_result(&path, res))
+}
+
+/// Reads the `atomic` flag from an options table. Default false.
+pub(crate) fn atomic_option(options: &Option<Table>) -> mlua::Result<bool> {
+    match options {
+        Some(tb) => Ok(tb.get::<Option<bool>>("atomic")?.unwrap_or(false)),
+        None => Ok(false),
+    }
+}
+
+/// Appends a string to a file, creating it if it does not exist.
+///
+/// # Returns
+///
+/// * `true`, or `false` and the error message if the file cannot be written.
+///
+/// # Example (in Lua)
+///
+/// ```lua
+/// fs.append_file("/tmp/my-file.log", "new line\n")
+/// ```
+pub(crate) fn append_file(_lua: &Lua, (path, value): (String, mlua::String)) -> mlua::Result<(bool, Option<String>)> {
+    let res = fs::OpenOptions::new().append(true).create(true).open(&path)
+        .and_then(|mut f| f.write_all(&value.as_bytes()));
+    Ok(bool_result(&path, res))
+}
+
+fn bool_result(path: &str, res: io::Result<()>) -> (bool, Option<String>) {
+    match res {
+        Ok(()) => (true, None),
+        Err(e) => (false, Some(format!("{}: {}", path, e))),
+    }
+}
+
+/// Returns the paths matching a glob pattern.
+///
+/// Supports `*`, `?`, `[...]` and `**` to match any number of directories. Entries that cannot be read
+/// are skipped.
+///
+/// # Arguments
+///
+/// * `_lua` - The Lua state (not used in this function).
+/// * `pattern` - The glob pattern, E.g. `src/**/*.rs`.
+///
+/// # Returns
+///
+/// * A vector of strings with the matching paths, sorted alphabetically.
+/// * Returns an error if the pattern is invalid.
+///
+/// # Example (in Lua)
+///
+/// ```lua
+/// for _, file in ipairs(fs.glob("src/**/*.rs")) do
+///     print(file)
+/// end
+/// ```
+pub(crate) fn glob(_lua: &Lua, pattern: String) -> mlua::Result<Vec<String>> {
+    let paths = glob::glob(&pattern)
+        .map_err(|e| mlua::Error::RuntimeError(format!("Invalid glob pattern {}: {}", pattern, e)))?;
+
+    let files = paths
+        .filter_map(|p| p.ok())
+        .map(|p| p.to_string_lossy().to_string())
+        .collect();
+
+    Ok(files)
+}
+
+/// Recursively lists the contents of a directory.
+///
+/// # Arguments
+///
+/// * `lua` - The Lua state.
+/// * `path` - The directory to walk.
+/// * `options` - Optional table containing:
+///   - `max_depth` (integer): Maximum depth to descend. 1 lists only the directory contents.
+///   - `follow_links` (bool): Follow symbolic links. Default false.
+///   - `include_hidden` (bool): Include entries starting with `.`. Default false.
+///   - `filter` (string or function): A glob matched against the entry name, or a function receiving the entry
+///     and returning true to keep it.
+///
+/// # Returns
+///
+/// * A table of entries `{ path, name, type, depth }`, where type is `file`, `dir`, `symlink` or `other`.
+/// * Returns an error if the path cannot be read.
+///
+/// # Example (in Lua)
+///
+/// ```lua
+/// local entries = fs.walk("src", { max_depth = 2, filter = "*.rs" })
+/// for _, entry in ipairs(entries) do
+///     print(entry.path, entry.type)
+/// end
+/// ```
+pub(crate) fn walk(lua: &Lua, (path, options): (String, Option<Table>)) -> mlua::Result<Table> {
+    let mut max_depth: Option<usize> = None;
+    let mut follow_links = false;
+    let mut include_hidden = false;
+    let mut filter = Value::Nil;
+    if let Some(ref tb) = options {
+        max_depth = tb.get("max_depth")?;
+        follow_links = tb.get::<Option<bool>>("follow_links")?.unwrap_or(false);
+        include_hidden = tb.get::<Option<bool>>("include_hidden")?.unwrap_or(false);
+        filter = tb.get("filter")?;
+    }
+
+    if !Path::new(&path).is_dir() {
+        return Err(io::Error::new(ErrorKind::InvalidInput, format!("{} is not a directory", path)).into());
+    }
+
+    let pattern = match filter {
+        Value::String(ref s) => Some(glob::Pattern::new(&s.to_str()?)
+            .map_err(|e| mlua::Error::RuntimeError(format!("Invalid glob pattern: {}", e)))?),
+        _ => None,
+    };
+
+    let mut walker = WalkDir::new(&path).min_depth(1).follow_links(follow_links);
+    if let Some(max_depth) = max_depth {
+        walker = walker.max_depth(max_depth);
+    }
+
+    let entries = lua.create_table()?;
+    let walker = walker.into_iter()
+        .filter_entry(|e| include_hidden || !is_hidden(e))
+        .filter_map(|e| e.ok());
+    for entry in walker {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if let Some(ref pattern) = pattern && !pattern.matches(&name) {
+            continue;
+        }
+
+        let entry_tb = lua.create_table()?;
+        entry_tb.set("path", entry.path().to_string_lossy())?;
+        entry_tb.set("name", name)?;
+        entry_tb.set("type", file_type_name(entry.file_type()))?;
+        entry_tb.set("depth", entry.depth())?;
+
+        if let Value::Function(ref f) = filter && !f.call::<bool>(&entry_tb)? {
+            continue;
+        }
+
+        entries.push(entry_tb)?;
+    }
+
+    Ok(entries)
+}
+
+fn is_hidden(entry: &DirEntry) -> bool {
+    entry.depth() > 0 && entry.file_name().to_str().is_some_and(|s| s.starts_with('.'))
+}
+
+fn file_type_name(file_type: fs::FileType) -> &'static str {
+    if file_type.is_symlink() {
+        "symlink"
+    } else if file_type.is_dir() {
+        "dir"
+    } else if file_type.is_file() {
+        "file"
+    } else {
+        "other"
+    }
+}
+
+/// Returns the metadata of a file, following symbolic links.
+///
+/// # Arguments
+///
+/// * `lua` - The Lua state.
+/// * `path` - The path to inspect.
+///
+/// # Returns
+///
+/// * A table with `path`, `type`, `size`, `mode`, `permissions`, `uid`, `gid`, `mtime`, `atime`, `ctime`,
+///   `is_symlink` and `readonly`. Times are in seconds since the unix epoch.
+/// * Returns an error if the path does not exist or cannot be read.
+///
+/// # Example (in Lua)
+///
+/// ```lua
+/// local st = fs.stat("/etc/hosts")
+/// print(st.size, st.permissions, st.mtime)
+/// ```
+pub(crate) fn stat(lua: &Lua, path: String) -> mlua::Result<Table> {
+    let md = fs::metadata(&path)?;
+    let is_symlink = fs::symlink_metadata(&path)?.file_type().is_symlink();
+    metadata_to_table(lua, &path, &md, is_symlink)
+}
+
+/// Returns the metadata of a file without following symbolic links.
+///
+/// Same as `stat`, but if the path is a symbolic link, the information is about the link itself.
+///
+/// # Example (in Lua)
+///
+/// ```lua
+/// local st = fs.lstat("/usr/bin/python")
+/// print(st.type) -- symlink
+/// ```
+pub(crate) fn lstat(lua: &Lua, path: String) -> mlua::Result<Table> {
+    let md = fs::symlink_metadata(&path)?;
+    metadata_to_table(lua, &path, &md, md.file_type().is_symlink())
+}
+
+/// Creates a symbolic link at `link` pointing to `target`.
+///
+/// The target is stored as given, so a relative target is relative to the directory of the link.
+///
+/// # Returns
+///
+/// * `true`, or `false` and the error message if the link cannot be created.
+///
+/// # Example (in Lua)
+///
+/// ```lua
+/// fs.symlink("app-1.2.0", "/opt/app/current")
+/// ```
+pub(crate) fn symlink(_lua: &Lua, (target, link): (String, String)) -> mlua::Result<(bool, Option<String>)> {
+    Ok(bool_result(&link, create_symlink(&target, &link)))
+}
+
+#[cfg(unix)]
+fn create_symlink(target: &str, link: &str) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+fn create_symlink(target: &str, link: &str) -> io::Result<()> {
+    let link_path = Path::new(link);
+    let resolved = link_path.parent().unwrap_or(Path::new(".")).join(target);
+    if resolved.is_dir() {
+        std::os::windows::fs::symlink_dir(target, link)
+    } else {
+        std::os::windows::fs::symlink_file(target, link)
+    }
+}
+
+/// Creates a hard link at `link` to the existing file `src`.
+///
+/// # Returns
+///
+/// * `true`, or `false` and the error message if the link cannot be created.
+///
+/// # Example (in Lua)
+///
+/// ```lua
+/// fs.hardlink("/backup/2024-01-01/data.db", "/backup/2024-01-02/data.db")
+/// ```
+pub(crate) fn hardlink(_lua: &Lua, (src, link): (String, String)) -> mlua::Result<(bool, Option<String>)> {
+    Ok(bool_result(&link, fs::hard_link(&src, &link)))
+}
+
+/// Returns the target of a symbolic link, as stored in the link.
+///
+/// # Returns
+///
+/// * The target, or `nil` and the error message if the path is not a symbolic link.
+///
+/// # Example (in Lua)
+///
+/// ```lua
+/// local target = fs.readlink("/opt/app/current")
+/// ```
+pub(crate) fn readlink(_lua: &Lua, path: String) -> mlua::Result<(Option<String>, Option<String>)> {
+    Ok(path_result(&path, fs::read_link(&path)))
+}
+
+/// Returns the absolute path with all symbolic links, `.` and `..` resolved.
+///
+/// # Returns
+///
+/// * The resolved path, or `nil` and the error message if the path does not exist.
+///
+/// # Example (in Lua)
+///
+/// ```lua
+/// local path = fs.realpath("../lib/current")
+/// ```
+pub(crate) fn realpath(_lua: &Lua, path: String) -> mlua::Result<(Option<String>, Option<String>)> {
+    Ok(path_result(&path, fs::canonicalize(&path)))
+}
+
+fn path_result(path: &str, res: io::Result<PathBuf>) -> (Option<String>, Option<String>) {
+    match res {
+        Ok(p) => (Some(p.to_string_lossy().to_string()), None),
+        Err(e) => (None, Some(format!("{}: {}", path, e))),
+    }
+}
+
+/// Changes the permissions of a file.
+///
+/// # Arguments
+///
+/// * `_lua` - The Lua state (not used in this function).
+/// * `path` - The file path.
+/// * `mode` - The permissions, as an octal string, E.g. `"755"` or `"4755"`, or as a number up to `0o777`, E.g.
+///   `tonumber("755", 8)`. Lua numbers are decimal, so `755` is rejected instead of setting the mode `0o1363`.
+///
+/// # Example (in Lua)
+///
+/// ```lua
+/// fs.chmod("/tmp/script.sh", "755")
+/// ```
+#[cfg(unix)]
+pub(crate) fn chmod(_lua: &Lua, (path, mode): (String, Value)) -> mlua::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let invalid_mode = |mode: String| mlua::Error::RuntimeError(format!(
+        "Invalid mode {}. Modes are octal, use a string like \"755\" or a number up to 0o777 (511)", mode));
+    let mode = match mode {
+        // Special bits need a string, so decimal typos like 755 are not taken as 0o1363
+        Value::Integer(n) => u32::try_from(n).ok().filter(|n| *n <= 0o777).ok_or_else(|| invalid_mode(n.to_string()))?,
+        Value::String(s) => u32::from_str_radix(&s.to_str()?, 8).ok().filter(|n| *n <= 0o7777)
+            .ok_or_else(|| invalid_mode(s.to_string_lossy()))?,
+        _ => return Err(mlua::Error::RuntimeError("Mode must be a number or an octal string".to_string())),
+    };
+    fs::set_permissions(&path, fs::Permissions::from_mode(mode))?;
+    Ok(())
+}
+
+/// Changes the owner and group of a file. A nil uid or gid is not changed.
+///
+/// # Example (in Lua)
+///
+/// ```lua
+/// fs.chown("/tmp/file.txt", 1000, 1000)
+/// fs.chown("/tmp/file.txt", nil, 100)
+/// ```
+#[cfg(unix)]
+pub(crate) fn chown(_lua: &Lua, (path, uid, gid): (String, Option<u32>, Option<u32>)) -> mlua::Result<()> {
+    std::os::unix::fs::chown(&path, uid, gid)?;
+    Ok(())
+}
+
+/// Creates an empty file if it does not exist, otherwise updates its access and modification times to now.
+///
+/// # Example (in Lua)
+///
+/// ```lua
+/// fs.touch("/tmp/marker")
+/// ```
+pub(crate) fn touch(_lua: &Lua, path: String) -> mlua::Result<()> {
+    let path = PathBuf::from(&path);
+    if !path.exists() {
+        fs::File::create(&path)?;
+    } else {
+        let now = FileTime::now();
+        set_file_times(&path, now, now)?;
+    }
+    Ok(())
+}
+
+/// Sets the modification time of a file.
+///
+/// # Arguments
+///
+/// * `_lua` - The Lua state (not used in this function).
+/// * `path` - The file path.
+/// * `mtime` - The modification time, in seconds since the unix epoch. Negative for times before the epoch.
+///
+/// # Example (in Lua)
+///
+/// ```lua
+/// fs.set_mtime("/tmp/file.txt", fs.stat("/tmp/other.txt").mtime)
+/// ```
+pub(crate) fn set_mtime(_lua: &Lua, (path, mtime): (String, f64)) -> mlua::Result<()> {
+    if !mtime.is_finite() {
+        return Err(mlua::Error::RuntimeError(format!("Invalid modification time: {}", mtime)));
+    }
+    let secs = mtime.floor();
+    let nanos = ((mtime - secs) * 1e9).min(999_999_999.0) as u32;
+    let mtime = FileTime::from_unix_time(secs as i64, nanos);
+    set_file_mtime(&path, mtime)?;
+    Ok(())
+}
+
+fn metadata_to_table(lua: &Lua, path: &str, md: &fs::Metadata, is_symlink: bool) -> mlua::Result<Table> {
+    let tb = lua.create_table()?;
+    tb.set("path", path)?;
+    tb.set("type", file_type_name(md.file_type()))?;
+    tb.set("size", md.len())?;
+    tb.set("is_symlink", is_symlink)?;
+    tb.set("readonly", md.permissions().readonly())?;
+    tb.set("mtime", md.modified().ok().map(system_time_secs))?;
+    tb.set("atime", md.accessed().ok().map(system_time_secs))?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        tb.set("mode", md.mode() & 0o7777)?;
+        tb.set("permissions", permissions_string(md.mode()))?;
+        tb.set("uid", md.uid())?;
+        tb.set("gid", md.gid())?;
+        tb.set("ctime", md.ctime())?;
+    }
+
+    Ok(tb)
+}
+
+fn system_time_secs(time: SystemTime) -> i64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_secs() as i64,
+        Err(e) => -(e.duration().as_secs() as i64),
+    }
+}
+
+/// Formats the permission bits like `ls -l`, E.g. `rwxr-xr-x`.
+#[cfg(unix)]
+fn permissions_string(mode: u32) -> String {
+    let flags = ['r', 'w', 'x'];
+    (0..9).map(|i| {
+        if mode & (0o400 >> i) != 0 { flags[i % 3] } else { '-' }
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::{tempdir, TempDir};
+
+    fn create_tree() -> TempDir {
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path();
+        fs::create_dir_all(root.join("src/modules")).unwrap();
+        fs::create_dir_all(root.join(".git")).unwrap();
+        fs::write(root.join("README.md"), "readme").unwrap();
+        fs::write(root.join("src/main.rs"), "main").unwrap();
+        fs::write(root.join("src/modules/fs.rs"), "fs").unwrap();
+        fs::write(root.join(".git/HEAD"), "head").unwrap();
+        temp_dir
+    }
+
+    fn entry_names(entries: &Table) -> Vec<String> {
+        let mut names: Vec<String> = entries.sequence_values::<Table>()
+            .map(|e| e.unwrap().get::<String>("name").unwrap())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_glob() {
+        let temp_dir = create_tree();
+        let lua = Lua::new();
+        let pattern = format!("{}/src/**/*.rs", temp_dir.path().display());
+        let files = glob(&lua, pattern).unwrap();
+        assert_eq!(files.len(), 2);
+        assert!(files[0].ends_with("src/main.rs"));
+        assert!(files[1].ends_with("src/modules/fs.rs"));
+
+        assert!(glob(&lua, "[".to_string()).is_err());
+    }
+
+    #[test]
+    fn test_walk() {
+        let temp_dir = create_tree();
+        let lua = Lua::new();
+        let path = temp_dir.path().to_str().unwrap().to_string();
+
+        let entries = walk(&lua, (path.clone(), None)).unwrap();
+        assert_eq!(entry_names(&entries), vec!["README.md", "fs.rs", "main.rs", "modules", "src"]);
+
+        let options = lua.create_table().unwrap();
+        options.set("max_depth", 1).unwrap();
+        options.set("include_hidden", true).unwrap();
+        let entries = walk(&lua, (path.clone(), Some(options))).unwrap();
+        assert_eq!(entry_names(&entries), vec![".git", "README.md", "src"]);
+
+        let options = lua.create_table().unwrap();
+        options.set("filter", "*.rs").unwrap();
+        let entries = walk(&lua, (path.clone(), Some(options))).unwrap();
+        assert_eq!(entry_names(&entries), vec!["fs.rs", "main.rs"]);
+
+        let options = lua.create_table().unwrap();
+        let filter = lua.load("function(e) return e.type == 'dir' end").eval::<mlua::Function>().unwrap();
+        options.set("filter", filter).unwrap();
+        let entries = walk(&lua, (path.clone(), Some(options))).unwrap();
+        assert_eq!(entry_names(&entries), vec!["modules", "src"]);
+
+        assert!(walk(&lua, (format!("{}/missing", path), None)).is_err());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_non_utf8_names() {
+        use std::os::unix::ffi::OsStrExt;
+        let temp_dir = tempdir().unwrap();
+        let lua = Lua::new();
+        let name = std::ffi::OsStr::from_bytes(b"bad\xffname.rs");
+        fs::write(temp_dir.path().join(name), "bad").unwrap();
+        let path = temp_dir.path().to_str().unwrap().to_string();
+
+        let entries = walk(&lua, (path.clone(), None)).unwrap();
+        assert_eq!(entry_names(&entries), vec!["bad\u{fffd}name.rs"]);
+        assert!(glob(&lua, format!("{}/*", path)).is_ok());
+    }
+
+    #[test]
+    fn test_is_file() {
+        let temp_dir = create_tree();
+        let lua = Lua::new();
+        let file = temp_dir.path().join("README.md").to_str().unwrap().to_string();
+        let dir = temp_dir.path().join("src").to_str().unwrap().to_string();
+        assert!(is_file(&lua, file).unwrap());
+        assert!(!is_file(&lua, dir).unwrap());
+    }
+
+    #[test]
+    fn test_stat_and_times() {
+        let temp_dir = create_tree();
+        let lua = Lua::new();
+        let file = temp_dir.path().join("README.md").to_str().unwrap().to_string();
+
+        set_mtime(&lua, (file.clone(), 1_000_000_000.0)).unwrap();
+        let st = stat(&lua, file.clone()).unwrap();
+        assert_eq!(st.get::<String>("type").unwrap(), "file");
+        assert_eq!(st.get::<u64>("size").unwrap(), 6);
+        assert_eq!(st.get::<i64>("mtime").unwrap(), 1_000_000_000);
+        assert!(!st.get::<bool>("is_symlink").unwrap());
+
+        touch(&lua, file.clone()).unwrap();
+        let st = stat(&lua, file.clone()).unwrap();
+        assert!(st.get::<i64>("mtime").unwrap() > 1_000_000_000);
+
+        let new_file = temp_dir.path().join("new.txt");
+        touch(&lua, new_file.to_str().unwrap().to_string()).unwrap();
+        assert!(new_file.is_file());
+
+        assert!(stat(&lua, temp_dir.path().join("missing").to_str().unwrap().to_string()).is_err());
+
+        // Times before the epoch round trip through stat
+        set_mtime(&lua, (file.clone(), -5.0)).unwrap();
+        let st = stat(&lua, file.clone()).unwrap();
+        assert_eq!(st.get::<i64>("mtime").unwrap(), -5);
+        assert!(set_mtime(&lua, (file.clone(), f64::NAN)).is_err());
+        assert!(set_mtime(&lua, (file.clone(), f64::INFINITY)).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_lstat_and_chmod() {
+        let temp_dir = create_tree();
+        let lua = Lua::new();
+        let file = temp_dir.path().join("README.md");
+        let link = temp_dir.path().join("link.md");
+        std::os::unix::fs::symlink(&file, &link).unwrap();
+        let file = file.to_str().unwrap().to_string();
+        let link = link.to_str().unwrap().to_string();
+
+        let st = lstat(&lua, link.clone()).unwrap();
+        assert_eq!(st.get::<String>("type").unwrap(), "symlink");
+        let st = stat(&lua, link.clone()).unwrap();
+        assert_eq!(st.get::<String>("type").unwrap(), "file");
+        assert!(st.get::<bool>("is_symlink").unwrap());
+
+        let mode = Value::String(lua.create_string("750").unwrap());
+        chmod(&lua, (file.clone(), mode)).unwrap();
+        let st = stat(&lua, file.clone()).unwrap();
+        assert_eq!(st.get::<u32>("mode").unwrap(), 0o750);
+        assert_eq!(st.get::<String>("permissions").unwrap(), "rwxr-x---");
+
+        for mode in [Value::Integer(755), Value::Integer(-1), Value::Integer(1 << 40)] {
+            assert!(chmod(&lua, (file.clone(), mode)).is_err());
+        }
+        let mode = Value::String(lua.create_string("17777").unwrap());
+        assert!(chmod(&lua, (file.clone(), mode)).is_err());
+
+        chmod(&lua, (file.clone(), Value::Integer(0o644))).unwrap();
+        let st = stat(&lua, file.clone()).unwrap();
+        assert_eq!(st.get::<String>("permissions").unwrap(), "rw-r--r--");
+
+        let uid = st.get::<u32>("uid").unwrap();
+        chown(&lua, (file.clone(), Some(uid), None)).unwrap();
+    }
+
+    #[test]
+    fn test_copy_recursive() {
+        let temp_dir = create_tree();
+        let lua = Lua::new();
+        let src = temp_dir.path().join("src").to_str().unwrap().to_string();
+        let target = temp_dir.path().join("backup").to_str().unwrap().to_string();
+
+        assert!(copy_file(&lua, (src.clone(), target.clone(), None)).is_err());
+
+        let options = lua.create_table().unwrap();
+        options.set("recursive", true).unwrap();
+        options.set("preserve", true).unwrap();
+        options.set("exclude", "main.rs").unwrap();
+        let copied = copy_file(&lua, (src.clone(), target.clone(), Some(options))).unwrap();
+        assert_eq!(copied, 1);
+        assert!(temp_dir.path().join("backup/modules/fs.rs").is_file());
+        assert!(!temp_dir.path().join("backup/main.rs").exists());
+
+        // Target exists, so the directory is copied inside it
+        let options = lua.create_table().unwrap();
+        options.set("recursive", true).unwrap();
+        copy_file(&lua, (src.clone(), target.clone(), Some(options))).unwrap();
+        assert!(temp_dir.path().join("backup/src/main.rs").is_file());
+
+        let file = temp_dir.path().join("README.md").to_str().unwrap().to_string();
+        let options = lua.create_table().unwrap();
+        options.set("overwrite", false).unwrap();
+        assert!(copy_file(&lua, (file.clone(), target.clone(), None)).is_ok());
+        assert!(copy_file(&lua, (file.clone(), target.clone(), Some(options))).is_err());
+
+        // The name of `..` is the name of the directory it resolves to
+        let out = temp_dir.path().join("out");
+        fs::create_dir(&out).unwrap();
+        let options = lua.create_table().unwrap();
+        options.set("recursive", true).unwrap();
+        let parent = format!("{}/modules/..", src);
+        copy_file(&lua, (parent, out.to_str().unwrap().to_string(), Some(options.clone()))).unwrap();
+        assert!(out.join("src/modules/fs.rs").is_file());
+
+        // A directory cannot be copied into itself
+        let inner = temp_dir.path().join("src/modules/inner").to_str().unwrap().to_string();
+        assert!(copy_file(&lua, (src.clone(), inner, Some(options.clone()))).is_err());
+        let own_dir = temp_dir.path().join("src/modules").to_str().unwrap().to_string();
+        assert!(copy_file(&lua, (src.clone(), own_dir, Some(options))).is_err());
+        assert!(!temp_dir.path().join("src/modules/inner").exists());
+        assert!(!temp_dir.path().join("src/modules/src").exists());
+    }
+
+    #[test]
+    fn test_sync() {
+        let temp_dir = create_tree();
+        let lua = Lua::new();
+        let src = temp_dir.path().join("src").to_str().unwrap().to_string();
+        let target = temp_dir.path().join("mirror");
+        let target_str = target.to_str().unwrap().to_string();
+
+        let res = sync(&lua, (src.clone(), target_str.clone(), None)).unwrap();
+        assert_eq!(res.get::<Table>("copied").unwrap().raw_len(), 2);
+        assert_eq!(res.get::<i64>("unchanged").unwrap(), 0);
+
+        let res = sync(&lua, (src.clone(), target_str.clone(), None)).unwrap();
+        assert_eq!(res.get::<Table>("copied").unwrap().raw_len(), 0);
+        assert_eq!(res.get::<i64>("unchanged").unwrap(), 2);
+
+        fs::write(temp_dir.path().join("src/main.rs"), "main changed").unwrap();
+        fs::write(target.join("extra.txt"), "extra").unwrap();
+
+        let options = lua.create_table().unwrap();
+        options.set("delete", true).unwrap();
+        options.set("dry_run", true).unwrap();
+        let res = sync(&lua, (src.clone(), target_str.clone(), Some(options))).unwrap();
+        assert_eq!(res.get::<Vec<String>>("updated").unwrap(), vec!["main.rs"]);
+        assert_eq!(res.get::<Vec<String>>("deleted").unwrap(), vec!["extra.txt"]);
+        assert!(target.join("extra.txt").exists());
+
+        let options = lua.create_table().unwrap();
+        options.set("delete", true).unwrap();
+        sync(&lua, (src.clone(), target_str.clone(), Some(options))).unwrap();
+        assert!(!target.join("extra.txt").exists());
+        assert_eq!(fs::read_to_string(target.join("main.rs")).unwrap(), "main changed");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_sync_symlinks() {
+        let temp_dir = create_tree();
+        let lua = Lua::new();
+        let src = temp_dir.path().join("src");
+        std::os::unix::fs::symlink("main.rs", src.join("link.rs")).unwrap();
+        let target = temp_dir.path().join("mirror");
+        let (src_str, target_str) = (src.to_str().unwrap().to_string(), target.to_str().unwrap().to_string());
+
+        sync(&lua, (src_str.clone(), target_str.clone(), None)).unwrap();
+        assert_eq!(fs::read_link(target.join("link.rs")).unwrap(), PathBuf::from("main.rs"));
+
+        // Links are compared by their target, not by their modification time
+        let res = sync(&lua, (src_str.clone(), target_str.clone(), None)).unwrap();
+        assert_eq!(res.get::<Table>("updated").unwrap().raw_len(), 0);
+        assert_eq!(res.get::<i64>("unchanged").unwrap(), 3);
+
+        fs::remove_file(src.join("link.rs")).unwrap();
+        std::os::unix::fs::symlink("modules/fs.rs", src.join("link.rs")).unwrap();
+        let res = sync(&lua, (src_str, target_str, None)).unwrap();
+        assert_eq!(res.get::<Vec<String>>("updated").unwrap(), vec!["link.rs"]);
+        assert_eq!(fs::read_link(target.join("link.rs")).unwrap(), PathBuf::from("modules/fs.rs"));
+    }
+
+    #[test]
+    fn test_sync_checksum() {
+        let temp_dir = create_tree();
+        let lua = Lua::new();
+        let src = temp_dir.path().join("src").to_str().unwrap().to_string();
+        let target = temp_dir.path().join("mirror");
+        let target_str = target.to_str().unwrap().to_string();
+        sync(&lua, (src.clone(), target_str.clone(), None)).unwrap();
+
+        // Same size and mtime, but different content
+        fs::write(target.join("main.rs"), "MAIN").unwrap();
+        copy_times(&temp_dir.path().join("src/main.rs"), &target.join("main.rs")).unwrap();
+
+        let res = sync(&lua, (src.clone(), target_str.clone(), None)).unwrap();
+        assert_eq!(res.get::<i64>("unchanged").unwrap(), 2);
+
+        let options = lua.create_table().unwrap();
+        options.set("checksum", true).unwrap();
+        let res = sync(&lua, (src.clone(), target_str.clone(), Some(options))).unwrap();
+        assert_eq!(res.get::<Vec<String>>("updated").unwrap(), vec!["main.rs"]);
+        assert_eq!(fs::read_to_string(target.join("main.rs")).unwrap(), "main");
+    }
+
+    #[test]
+    fn test_move() {
+        let temp_dir = create_tree();
+        let lua = Lua::new();
+        let file = temp_dir.path().join("README.md").to_str().unwrap().to_string();
+        let src = temp_dir.path().join("src").to_str().unwrap().to_string();
+        let target = temp_dir.path().join("moved").to_str().unwrap().to_string();
+
+        move_file(&lua, (src.clone(), target.clone(), None)).unwrap();
+        assert!(temp_dir.path().join("moved/modules/fs.rs").is_file());
+        assert!(!temp_dir.path().join("src").exists());
+
+        fs::write(temp_dir.path().join("moved/README.md"), "old").unwrap();
+        let options = lua.create_table().unwrap();
+        options.set("overwrite", false).unwrap();
+        assert!(move_file(&lua, (file.clone(), target.clone(), Some(options))).is_err());
+
+        move_file(&lua, (file.clone(), target.clone(), None)).unwrap();
+        assert_eq!(fs::read_to_string(temp_dir.path().join("moved/README.md")).unwrap(), "readme");
+
+        // Moving a directory into its own parent resolves to itself and must not delete it
+        let modules = temp_dir.path().join("moved/modules").to_str().unwrap().to_string();
+        let options = lua.create_table().unwrap();
+        options.set("overwrite", true).unwrap();
+        assert!(move_file(&lua, (modules.clone(), target.clone(), Some(options))).is_err());
+        assert!(move_file(&lua, (target.clone(), modules.clone(), None)).is_err());
+        assert!(temp_dir.path().join("moved/modules/fs.rs").is_file());
+
+        // An existing directory with contents is only replaced with replace_dir, and no backup is left behind
+        let other = temp_dir.path().join("other");
+        fs::create_dir_all(other.join("modules")).unwrap();
+        fs::write(other.join("modules/old.rs"), "old").unwrap();
+        let other_str = other.to_str().unwrap().to_string();
+        assert!(move_file(&lua, (modules.clone(), other_str.clone(), None)).is_err());
+        assert!(other.join("modules/old.rs").is_file());
+        assert!(temp_dir.path().join("moved/modules/fs.rs").is_file());
+
+        let options = lua.create_table().unwrap();
+        options.set("replace_dir", true).unwrap();
+        move_file(&lua, (modules.clone(), other_str, Some(options))).unwrap();
+        assert!(other.join("modules/fs.rs").is_file());
+        assert!(!other.join("modules/old.rs").exists());
+        assert_eq!(fs::read_dir(&other).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_copy_for_move() {
+        let temp_dir = create_tree();
+        let lua = Lua::new();
+        let src = temp_dir.path().join("src");
+        let target = temp_dir.path().join("moved");
+        set_mtime(&lua, (src.join("main.rs").to_str().unwrap().to_string(), 1_000_000_000.0)).unwrap();
+        set_mtime(&lua, (src.join("modules").to_str().unwrap().to_string(), 1_000_000_000.0)).unwrap();
+
+        copy_for_move(&src, &target).unwrap();
+        let md = fs::metadata(target.join("main.rs")).unwrap();
+        assert_eq!(FileTime::from_last_modification_time(&md).unix_seconds(), 1_000_000_000);
+        let md = fs::metadata(target.join("modules")).unwrap();
+        assert_eq!(FileTime::from_last_modification_time(&md).unix_seconds(), 1_000_000_000);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_links() {
+        let temp_dir = create_tree();
+        let lua = Lua::new();
+        let root = temp_dir.path();
+        let path = |p: &str| root.join(p).to_str().unwrap().to_string();
+
+        assert_eq!(symlink(&lua, ("src".to_string(), path("src-link"))).unwrap(), (true, None));
+        assert_eq!(symlink(&lua, ("missing".to_string(), path("dangling"))).unwrap(), (true, None));
+        assert!(!symlink(&lua, ("src".to_string(), path("src-link"))).unwrap().0);
+        assert_eq!(hardlink(&lua, (path("README.md"), path("README.hard"))).unwrap(), (true, None));
+
+        assert_eq!(readlink(&lua, path("src-link")).unwrap(), (Some("src".to_string()), None));
+        assert!(readlink(&lua, path("README.md")).unwrap().1.is_some());
+        let real = realpath(&lua, path("src-link/modules/../main.rs")).unwrap().0.unwrap();
+        assert_eq!(PathBuf::from(real), root.canonicalize().unwrap().join("src/main.rs"));
+        assert!(realpath(&lua, path("dangling")).unwrap().0.is_none());
+
+        // Links are listed, even when they point to nothing
+        let dir = Value::String(lua.create_string(path("")).unwrap());
+        let (entries, _) = ls(&lua, Variadic::from_iter([dir])).unwrap();
+        let entries: Vec<String> = entries.sequence_values().map(|e| e.unwrap()).collect();
+        assert!(entries.contains(&path("dangling")));
+
+        fs::write(root.join("README.hard"), "changed").unwrap();
+        assert_eq!(fs::read_to_string(root.join("README.md")).unwrap(), "changed");
+
+        // rmdir refuses the link, rm removes only the link and not the directory contents
+        assert!(rmdir(&lua, (path("src-link"), None)).is_err());
+        let options = lua.create_table().unwrap();
+        options.set("recursive", true).unwrap();
+        assert!(delete_file(&lua, (path("src-link"), Some(options))).unwrap());
+        assert!(delete_file(&lua, (path("dangling"), None)).unwrap());
+        assert!(root.join("src/main.rs").is_file());
+        assert!(fs::symlink_metadata(root.join("src-link")).is_err());
+        assert!(fs::symlink_metadata(root.join("dangling")).is_err());
+
+        // Recursive delete of a directory containing a link to the outside
+        symlink(&lua, (path("src"), path(".git/outside"))).unwrap();
+        let options = lua.create_table().unwrap();
+        options.set("recursive", true).unwrap();
+        assert!(delete_file(&lua, (path(".git"), Some(options))).unwrap());
+        assert!(root.join("src/modules/fs.rs").is_file());
+    }
+
+    #[test]
+    fn test_ls() {
+        let temp_dir = create_tree();
+        let lua = Lua::new();
+        let root = temp_dir.path();
+        fs::write(root.join("a.log"), "12345").unwrap();
+        fs::write(root.join("b.log"), "1").unwrap();
+        set_mtime(&lua, (root.join("a.log").to_str().unwrap().to_string(), 1_000_000_000.0)).unwrap();
+        set_mtime(&lua, (root.join("b.log").to_str().unwrap().to_string(), 1_500_000_000.0)).unwrap();
+
+        let dir = Value::String(lua.create_string(root.to_str().unwrap()).unwrap());
+        let (entries, errors) = ls(&lua, Variadic::from_iter([dir.clone()])).unwrap();
+        assert!(errors.is_none());
+        let paths: Vec<String> = entries.sequence_values().map(|e| e.unwrap()).collect();
+        let names: Vec<&str> = paths.iter().map(|p| p.rsplit('/').next().unwrap()).collect();
+        assert_eq!(names, vec![".git", "README.md", "a.log", "b.log", "src"]);
+
+        let options = lua.create_table().unwrap();
+        options.set("long", true).unwrap();
+        options.set("sort", "mtime").unwrap();
+        options.set("reverse", true).unwrap();
+        options.set("filter", "*.log").unwrap();
+        let (entries, _) = ls(&lua, Variadic::from_iter([dir.clone(), Value::Table(options)])).unwrap();
+        let first: Table = entries.get(1).unwrap();
+        assert_eq!(entries.len().unwrap(), 2);
+        assert_eq!(first.get::<String>("name").unwrap(), "b.log");
+        assert_eq!(first.get::<String>("type").unwrap(), "file");
+        assert_eq!(first.get::<u64>("size").unwrap(), 1);
+        assert_eq!(first.get::<i64>("mtime").unwrap(), 1_500_000_000);
+
+        let options = lua.create_table().unwrap();
+        options.set("all", false).unwrap();
+        options.set("sort", "size").unwrap();
+        let (entries, _) = ls(&lua, Variadic::from_iter([dir.clone(), Value::Table(options)])).unwrap();
+        assert_eq!(entries.len().unwrap(), 4);
+
+        let options = lua.create_table().unwrap();
+        options.set("sort", "color").unwrap();
+        assert!(ls(&lua, Variadic::from_iter([dir, Value::Table(options)])).is_err());
+    }
+
+    #[test]
+    fn test_same() {
+        let temp_dir = create_tree();
+        let lua = Lua::new();
+        let path = |p: &str| temp_dir.path().join(p).to_str().unwrap().to_string();
+        fs::write(temp_dir.path().join("copy.md"), "readme").unwrap();
+        fs::write(temp_dir.path().join("other.md"), "readmf").unwrap();
+
+        assert!(same(&lua, (path("README.md"), path("copy.md"))).unwrap());
+        assert!(!same(&lua, (path("README.md"), path("other.md"))).unwrap());
+        assert!(!same(&lua, (path("README.md"), path("src/main.rs"))).unwrap());
+        assert!(same(&lua, (path("README.md"), path("missing"))).is_err());
+    }
+}
@@ -0,0 +1,273 @@
+use std::fs;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use mlua::{Lua, Table, Value};
+use regex::{Regex, RegexBuilder};
+use walkdir::WalkDir;
+use crate::utils::atomic_write::write_atomic;
+
+/// A line matching the pattern in `fs.grep`.
+struct GrepMatch {
+    file: String,
+    line: usize,
+    column: usize,
+    text: String,
+}
+
+fn build_regex(pattern: &str, ignore_case: bool) -> mlua::Result<Regex> {
+    RegexBuilder::new(pattern)
+        .case_insensitive(ignore_case)
+        .build()
+        .map_err(|e| mlua::Error::RuntimeError(format!("Invalid regex {}: {}", pattern, e)))
+}
+
+/// Searches files for lines matching a regular expression.
+///
+/// Binary files, detected by a NUL byte, are skipped.
+///
+/// # Arguments
+///
+/// * `lua` - The Lua state.
+/// * `pattern` - The regular expression.
+/// * `paths` - A file or directory, or a table of them.
+/// * `options` - Optional table containing:
+///   - `recursive` (bool): Search the files inside directories. Default false, directories are reported as errors.
+///   - `ignore_case` (bool): Case-insensitive match. Default false.
+///   - `glob` (string or table): Only search files whose name matches one of the globs, E.g. `"*.rs"`.
+///
+/// # Returns
+///
+/// * A table of matches `{ file, line, column, text }`, with 1-based line and column of the first match in the line.
+/// * A table with the error messages of the files that could not be read, or `nil`.
+///
+/// # Example (in Lua)
+///
+/// ```lua
+/// local matches = fs.grep("TODO|FIXME", "src", { recursive = true, glob = "*.rs" })
+/// for _, m in ipairs(matches) do
+///     print(m.file .. ":" .. m.line .. ": " .. m.text)
+/// end
+/// ```
+pub(crate) fn grep(lua: &Lua, (pattern, paths, options): (String, Value, Option<Table>)) -> mlua::Result<(Table, Option<Vec<String>>)> {
+    let paths: Vec<String> = match paths {
+        Value::Table(tb) => tb.sequence_values::<String>().collect::<mlua::Result<_>>()?,
+        value => vec![value.to_string()?],
+    };
+
+    let mut recursive = false;
+    let mut ignore_case = false;
+    let mut globs = vec![];
+    if let Some(ref tb) = options {
+        recursive = tb.get::<Option<bool>>("recursive")?.unwrap_or(false);
+        ignore_case = tb.get::<Option<bool>>("ignore_case")?.unwrap_or(false);
+        globs = crate::modules::filesystem::glob_patterns(tb.get("glob")?)?;
+    }
+    let re = build_regex(&pattern, ignore_case)?;
+
+    let mut matches = vec![];
+    let mut errors = vec![];
+    for path in paths.iter() {
+        if !Path::new(path).is_dir() {
+            // Files given explicitly are searched even if they don't match the globs
+            if let Err(e) = grep_file(&re, Path::new(path), &mut matches) {
+                errors.push(format!("{}: {}", path, e));
+            }
+            continue;
+        }
+
+        if !recursive {
+            errors.push(format!("{}: Is a directory", path));
+            continue;
+        }
+
+        for entry in WalkDir::new(path).sort_by_file_name() {
+            let entry = match entry {
+                Ok(e) => e,
+                Err(e) => {
+                    errors.push(e.to_string());
+                    continue;
+                }
+            };
+            let name = entry.file_name().to_string_lossy();
+            if !entry.file_type().is_file() || (!globs.is_empty() && !globs.iter().any(|g| g.matches(&name))) {
+                continue;
+            }
+            if let Err(e) = grep_file(&re, entry.path(), &mut matches) {
+                errors.push(format!("{}: {}", entry.path().display(), e));
+            }
+        }
+    }
+
+    let res = lua.create_table()?;
+    for m in matches {
+        let match_tb = lua.create_table()?;
+        match_tb.set("file", m.file)?;
+        match_tb.set("line", m.line)?;
+        match_tb.set("column", m.column)?;
+        match_tb.set("text", m.text)?;
+        res.push(match_tb)?;
+    }
+
+    let errors = if errors.is_empty() { None } else { Some(errors) };
+    Ok((res, errors))
+}
+
+fn grep_file(re: &Regex, path: &Path, matches: &mut Vec<GrepMatch>) -> std::io::Result<()> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut buf = vec![];
+    let mut line_number = 0;
+    let mut file_matches = vec![];
+    loop {
+        buf.clear();
+        if reader.read_until(b'\n', &mut buf)? == 0 {
+            break;
+        }
+        if buf.contains(&0) {
+            // Binary file
+            return Ok(());
+        }
+
+        line_number += 1;
+        let line = String::from_utf8_lossy(&buf);
+        let line = line.trim_end_matches(['\n', '\r']);
+        if let Some(m) = re.find(line) {
+            file_matches.push(GrepMatch {
+                file: path.to_string_lossy().to_string(),
+                line: line_number,
+                column: line[..m.start()].chars().count() + 1,
+                text: line.to_string(),
+            });
+        }
+    }
+
+    matches.extend(file_matches);
+    Ok(())
+}
+
+/// Replaces the matches of a regular expression in a file.
+///
+/// The replacement can refer to capture groups with `$1` or `${name}`. Files edited in place are written
+/// atomically.
+///
+/// # Arguments
+///
+/// * `lua` - The Lua state.
+/// * `path` - The file to edit.
+/// * `pattern` - The regular expression.
+/// * `replacement` - The replacement text.
+/// * `options` - Optional table containing:
+///   - `in_place` (bool): Writes the result to the file. Default true.
+///   - `backup` (string): Suffix of a copy of the original file, E.g. `".bak"`. Only when editing in place.
+///   - `ignore_case` (bool): Case-insensitive match. Default false.
+///
+/// # Returns
+///
+/// * The number of replacements and, when not editing in place, the new content.
+///
+/// # Example (in Lua)
+///
+/// ```lua
+/// local count = fs.sed("config.ini", [[^port\s*=.*$]], "port = 8080", { backup = ".bak" })
+/// local _, content = fs.sed("template.txt", "\\{\\{name\\}\\}", "lush", { in_place = false })
+/// ```
+pub(crate) fn sed(lua: &Lua, (path, pattern, replacement, options): (String, String, String, Option<Table>)) -> mlua::Result<(usize, Option<mlua::String>)> {
+    let mut in_place = true;
+    let mut backup: Option<String> = None;
+    let mut ignore_case = false;
+    if let Some(ref tb) = options {
+        in_place = tb.get::<Option<bool>>("in_place")?.unwrap_or(true);
+        backup = tb.get("backup")?;
+        ignore_case = tb.get::<Option<bool>>("ignore_case")?.unwrap_or(false);
+    }
+
+    // Multi-line mode, so ^ and $ match each line as in sed. In CRLF mode, `$` matches before `\r\n` and `.` does
+    // not match `\r`, so the line ends of Windows files are kept
+    let re = build_regex(&format!("(?mR){}", pattern), ignore_case)?;
+    let content = fs::read_to_string(&path)
+        .map_err(|e| mlua::Error::RuntimeError(format!("{}: {}", path, e)))?;
+
+    let count = re.find_iter(&content).count();
+    let new_content = re.replace_all(&content, replacement.as_str());
+
+    if !in_place {
+        return Ok((count, Some(lua.create_string(new_content.as_bytes())?)));
+    }
+
+    if count > 0 {
+        if let Some(suffix) = backup {
+            fs::copy(&path, format!("{}{}", path, suffix))?;
+        }
+        write_atomic(&path, new_content.as_bytes())?;
+    }
+    Ok((count, None))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_grep() {
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path();
+        fs::create_dir(root.join("src")).unwrap();
+        fs::write(root.join("src/main.rs"), "fn main() {\n    // TODO: args\n}\n").unwrap();
+        fs::write(root.join("src/notes.txt"), "todo: ignored by the glob\n").unwrap();
+        fs::write(root.join("src/data.bin"), b"TODO\0binary").unwrap();
+        fs::write(root.join("README.md"), "Nothing to do\n  Todo: docs\n").unwrap();
+        let lua = Lua::new();
+        let root_str = root.to_str().unwrap().to_string();
+
+        let options = lua.create_table().unwrap();
+        options.set("recursive", true).unwrap();
+        options.set("ignore_case", true).unwrap();
+        options.set("glob", vec!["*.rs", "*.md", "*.bin"]).unwrap();
+        let (matches, errors) = grep(&lua, ("todo".to_string(), Value::String(lua.create_string(&root_str).unwrap()), Some(options))).unwrap();
+        assert!(errors.is_none());
+        assert_eq!(matches.raw_len(), 2);
+
+        let first: Table = matches.get(1).unwrap();
+        assert!(first.get::<String>("file").unwrap().ends_with("README.md"));
+        assert_eq!(first.get::<usize>("line").unwrap(), 2);
+        assert_eq!(first.get::<usize>("column").unwrap(), 3);
+        assert_eq!(first.get::<String>("text").unwrap(), "  Todo: docs");
+        let second: Table = matches.get(2).unwrap();
+        assert!(second.get::<String>("file").unwrap().ends_with("main.rs"));
+
+        // Case-sensitive, directory without recursive
+        let (matches, errors) = grep(&lua, ("todo".to_string(), Value::String(lua.create_string(&root_str).unwrap()), None)).unwrap();
+        assert_eq!(matches.raw_len(), 0);
+        assert_eq!(errors.unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_sed() {
+        let temp_dir = tempdir().unwrap();
+        let file = temp_dir.path().join("config.ini");
+        fs::write(&file, "host = localhost\nport = 80\n").unwrap();
+        let lua = Lua::new();
+        let path = file.to_str().unwrap().to_string();
+
+        let options = lua.create_table().unwrap();
+        options.set("in_place", false).unwrap();
+        let (count, content) = sed(&lua, (path.clone(), r"^(\w+) = ".to_string(), "$1: ".to_string(), Some(options))).unwrap();
+        assert_eq!(content.unwrap().to_str().unwrap(), "host: localhost\nport: 80\n");
+        assert_eq!(count, 2);
+
+        let options = lua.create_table().unwrap();
+        options.set("backup", ".bak").unwrap();
+        let (count, content) = sed(&lua, (path.clone(), r"^port = .*$".to_string(), "port = 8080".to_string(), Some(options))).unwrap();
+        assert_eq!(count, 1);
+        assert!(content.is_none());
+        assert_eq!(fs::read_to_string(&file).unwrap(), "host = localhost\nport = 8080\n");
+        assert_eq!(fs::read_to_string(format!("{}.bak", path)).unwrap(), "host = localhost\nport = 80\n");
+
+        // CRLF line ends are kept
+        fs::write(&file, "host = localhost\r\nport = 80\r\n").unwrap();
+        let (count, _) = sed(&lua, (path.clone(), r"^port = .*$".to_string(), "port = 8080".to_string(), None)).unwrap();
+        assert_eq!(count, 1);
+        assert_eq!(fs::read_to_string(&file).unwrap(), "host = localhost\r\nport = 8080\r\n");
+    }
+}
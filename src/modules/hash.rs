@@ -0,0 +1,299 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use mlua::{Lua, Table};
+use crate::utils::hash::{hash_bytes, hash_file as hash_file_int, parse_checksum_line, Hasher};
+
+const DEFAULT_ALGORITHM: &str = "sha256";
+
+/// Returns the hex digest of a file, reading it in chunks.
+///
+/// # Arguments
+///
+/// * `_lua` - The Lua state (not used in this function).
+/// * `path` - The file to hash.
+/// * `algorithm` - Optional. `sha256` (default), `sha512`, `sha1`, `md5` or `blake3`.
+///
+/// # Returns
+///
+/// * The hash, or `nil` and the error message if the file cannot be read.
+/// * Returns an error if the algorithm is not supported.
+///
+/// # Example (in Lua)
+///
+/// ```lua
+/// local digest = fs.hash("/tmp/release.tar.zst", "blake3")
+/// ```
+pub(crate) fn hash_file(_lua: &Lua, (path, algorithm): (String, Option<String>)) -> mlua::Result<(Option<String>, Option<String>)> {
+    let algorithm = algorithm.unwrap_or(DEFAULT_ALGORITHM.to_string());
+    Hasher::new(&algorithm)?;
+
+    match hash_file_int(&path, &algorithm) {
+        Ok(digest) => Ok((Some(digest), None)),
+        Err(e) => Ok((None, Some(format!("{}: {}", path, e)))),
+    }
+}
+
+/// Returns the hex digest of a string.
+///
+/// # Example (in Lua)
+///
+/// ```lua
+/// print(string.hash("hello", "md5"))
+/// ```
+pub(crate) fn hash_string(_lua: &Lua, (data, algorithm): (mlua::String, Option<String>)) -> mlua::Result<String> {
+    let algorithm = algorithm.unwrap_or(DEFAULT_ALGORITHM.to_string());
+    Ok(hash_bytes(&data.as_bytes(), &algorithm)?)
+}
+
+/// Checks if the hash of a file matches the expected one.
+///
+/// The algorithm can be given as argument, as prefix of the expected hash (E.g. `sha1:2fd4...`) or is guessed from
+/// the hash length: md5, sha1, sha256 or sha512. The comparison is case-insensitive.
+///
+/// # Returns
+///
+/// * `true`, or `false` and the reason, if the hash does not match or the file cannot be read.
+/// * Returns an error if the algorithm is not supported.
+///
+/// # Example (in Lua)
+///
+/// ```lua
+/// local ok, err = fs.verify("/tmp/lush.tar.gz", "sha256:9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08")
+/// ```
+pub(crate) fn verify(_lua: &Lua, (path, expected, algorithm): (String, String, Option<String>)) -> mlua::Result<(bool, Option<String>)> {
+    let (algorithm, hash) = checksum_algorithm(&expected, algorithm);
+    Hasher::new(&algorithm)?;
+
+    match verify_checksum(&path, hash, Some(algorithm)) {
+        Ok(()) => Ok((true, None)),
+        Err(msg) => Ok((false, Some(msg))),
+    }
+}
+
+/// Checks the hash of a file, as `fs.verify`.
+///
+/// # Returns
+///
+/// * The error message if the file can't be read or the hash is different.
+pub(crate) fn verify_checksum(path: &str, expected: &str, algorithm: Option<String>) -> Result<(), String> {
+    let (algorithm, expected) = checksum_algorithm(expected, algorithm);
+    let actual = hash_file_int(path, &algorithm).map_err(|e| format!("{}: {}", path, e))?;
+
+    if actual.eq_ignore_ascii_case(expected.trim()) {
+        Ok(())
+    } else {
+        Err(format!("{}: {} mismatch, expected {} but got {}", path, algorithm, expected, actual))
+    }
+}
+
+/// Returns the algorithm of an expected hash and the hash without the algorithm prefix, E.g. `sha1:2fd4...`.
+pub(crate) fn checksum_algorithm(expected: &str, algorithm: Option<String>) -> (String, &str) {
+    match (algorithm, expected.split_once(':')) {
+        (Some(algorithm), _) => (algorithm, expected),
+        (None, Some((prefix, hash))) => (prefix.to_string(), hash),
+        (None, None) => (guess_algorithm(expected).to_string(), expected),
+    }
+}
+
+/// Guesses the algorithm of a hex digest by its length. blake3 has the same length as sha256, so it has to be
+/// given explicitly.
+pub(crate) fn guess_algorithm(hash: &str) -> &'static str {
+    match hash.trim().len() {
+        32 => "md5",
+        40 => "sha1",
+        128 => "sha512",
+        _ => DEFAULT_ALGORITHM,
+    }
+}
+
+/// Reads a checksum manifest in the format of `sha256sum` and similar tools, `<hash>  <file name>` per line.
+///
+/// # Returns
+///
+/// * A table mapping the file names to their hashes.
+/// * Returns an error if the manifest cannot be read or has an invalid line.
+///
+/// # Example (in Lua)
+///
+/// ```lua
+/// local sums = fs.read_checksums("SHA256SUMS")
+/// print(sums["lush-linux-x64.tar.gz"])
+/// ```
+pub(crate) fn read_checksums(lua: &Lua, manifest: String) -> mlua::Result<Table> {
+    let entries = lua.create_table()?;
+    for (name, hash) in read_manifest(Path::new(&manifest))? {
+        entries.set(name, hash)?;
+    }
+    Ok(entries)
+}
+
+fn read_manifest(manifest: &Path) -> mlua::Result<Vec<(String, String)>> {
+    let content = fs::read_to_string(manifest)
+        .map_err(|e| mlua::Error::RuntimeError(format!("{}: {}", manifest.display(), e)))?;
+
+    let mut entries = vec![];
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match parse_checksum_line(line) {
+            Some((hash, name)) => entries.push((name.to_string(), hash.to_lowercase())),
+            None => return Err(mlua::Error::RuntimeError(
+                format!("{}:{}: invalid checksum line", manifest.display(), i + 1))),
+        }
+    }
+    Ok(entries)
+}
+
+/// Writes a checksum manifest with the hashes of the given files.
+///
+/// Files inside the directory of the manifest are written relative to it, so the manifest can be checked with
+/// `sha256sum -c` from that directory.
+///
+/// # Arguments
+///
+/// * `_lua` - The Lua state (not used in this function).
+/// * `manifest` - The manifest file to write.
+/// * `files` - The files to hash.
+/// * `algorithm` - Optional. `sha256` (default), `sha512`, `sha1`, `md5` or `blake3`.
+///
+/// # Example (in Lua)
+///
+/// ```lua
+/// fs.write_checksums("dist/SHA256SUMS", fs.glob("dist/*.tar.gz"))
+/// ```
+pub(crate) fn write_checksums(_lua: &Lua, (manifest, files, algorithm): (String, Vec<String>, Option<String>)) -> mlua::Result<()> {
+    let algorithm = algorithm.unwrap_or(DEFAULT_ALGORITHM.to_string());
+    let base_dir = manifest_dir(Path::new(&manifest));
+
+    let mut content = String::new();
+    for file in files.iter() {
+        let hash = hash_file_int(file, &algorithm)
+            .map_err(|e| mlua::Error::RuntimeError(format!("{}: {}", file, e)))?;
+        let name = Path::new(file).strip_prefix(&base_dir).unwrap_or(Path::new(file));
+        content.push_str(&format!("{}  {}\n", hash, name.display()));
+    }
+
+    fs::write(&manifest, content)?;
+    Ok(())
+}
+
+/// Checks all the files listed in a checksum manifest. Relative file names are relative to the manifest directory.
+///
+/// Without `algorithm`, the algorithm of each hash is guessed from its length, so `SHA512SUMS` or `MD5SUMS`
+/// manifests can be checked too.
+///
+/// # Returns
+///
+/// * `true` if all files match, otherwise `false` and a table with the names of the files that do not match or
+///   cannot be read.
+/// * Returns an error if the algorithm is not supported.
+///
+/// # Example (in Lua)
+///
+/// ```lua
+/// local ok, failed = fs.check_checksums("backup/SHA256SUMS")
+/// ```
+pub(crate) fn check_checksums(lua: &Lua, (manifest, algorithm): (String, Option<String>)) -> mlua::Result<(bool, Table)> {
+    if let Some(ref algorithm) = algorithm {
+        Hasher::new(algorithm)?;
+    }
+    let manifest = Path::new(&manifest);
+    let base_dir = manifest_dir(manifest);
+
+    let failed = lua.create_table()?;
+    for (name, expected) in read_manifest(manifest)? {
+        let algorithm = algorithm.clone().unwrap_or(guess_algorithm(&expected).to_string());
+        match hash_file_int(base_dir.join(&name), &algorithm) {
+            Ok(actual) if actual == expected => {}
+            _ => failed.push(name)?,
+        }
+    }
+
+    Ok((failed.raw_len() == 0, failed))
+}
+
+fn manifest_dir(manifest: &Path) -> PathBuf {
+    match manifest.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    const HELLO_SHA256: &str = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+
+    #[test]
+    fn test_hash_and_verify() {
+        let temp_dir = tempdir().unwrap();
+        let file = temp_dir.path().join("hello.txt").to_str().unwrap().to_string();
+        fs::write(&file, "hello").unwrap();
+        let lua = Lua::new();
+
+        assert_eq!(hash_file(&lua, (file.clone(), None)).unwrap().0.unwrap(), HELLO_SHA256);
+        assert!(hash_file(&lua, (file.clone(), Some("crc".to_string()))).is_err());
+        assert!(hash_file(&lua, (format!("{}.missing", file), None)).unwrap().1.is_some());
+
+        assert!(verify(&lua, (file.clone(), HELLO_SHA256.to_uppercase(), None)).unwrap().0);
+        assert!(verify(&lua, (file.clone(), "md5:5d41402abc4b2a76b9719d911017c592".to_string(), None)).unwrap().0);
+        assert!(verify(&lua, (file.clone(), "aaf4c61ddcc5e8a2dabede0f3b482cd9aea9434d".to_string(), None)).unwrap().0);
+        let (ok, err) = verify(&lua, (file.clone(), HELLO_SHA256.to_string(), Some("blake3".to_string()))).unwrap();
+        assert!(!ok);
+        assert!(err.unwrap().contains("mismatch"));
+
+        // Unknown algorithms are errors, as in fs.hash
+        assert!(verify(&lua, (file.clone(), "crc:1234".to_string(), None)).is_err());
+        assert!(verify(&lua, (file.clone(), HELLO_SHA256.to_string(), Some("crc".to_string()))).is_err());
+
+        let sha512 = hash_file(&lua, (file.clone(), Some("sha512".to_string()))).unwrap().0.unwrap();
+        assert_eq!(sha512.len(), 128);
+        assert!(verify(&lua, (file.clone(), sha512, None)).unwrap().0);
+    }
+
+    #[test]
+    fn test_checksums_manifest() {
+        let temp_dir = tempdir().unwrap();
+        let root = temp_dir.path();
+        fs::create_dir(root.join("dist")).unwrap();
+        fs::write(root.join("dist/a.txt"), "hello").unwrap();
+        fs::write(root.join("dist/b.txt"), "world").unwrap();
+        let manifest = root.join("dist/SHA256SUMS").to_str().unwrap().to_string();
+        let files = vec![
+            root.join("dist/a.txt").to_str().unwrap().to_string(),
+            root.join("dist/b.txt").to_str().unwrap().to_string(),
+        ];
+        let lua = Lua::new();
+
+        write_checksums(&lua, (manifest.clone(), files, None)).unwrap();
+        let content = fs::read_to_string(&manifest).unwrap();
+        assert!(content.starts_with(&format!("{}  a.txt\n", HELLO_SHA256)));
+
+        let sums = read_checksums(&lua, manifest.clone()).unwrap();
+        assert_eq!(sums.get::<String>("a.txt").unwrap(), HELLO_SHA256);
+
+        let (ok, failed) = check_checksums(&lua, (manifest.clone(), None)).unwrap();
+        assert!(ok);
+        assert_eq!(failed.raw_len(), 0);
+
+        fs::write(root.join("dist/b.txt"), "changed").unwrap();
+        let (ok, failed) = check_checksums(&lua, (manifest.clone(), None)).unwrap();
+        assert!(!ok);
+        assert_eq!(failed.get::<String>(1).unwrap(), "b.txt");
+
+        assert!(check_checksums(&lua, (manifest.clone(), Some("crc".to_string()))).is_err());
+
+        // The algorithm of a SHA512SUMS manifest is guessed from the hash length
+        let sha512_manifest = root.join("dist/SHA512SUMS").to_str().unwrap().to_string();
+        let files = vec![root.join("dist/a.txt").to_str().unwrap().to_string()];
+        write_checksums(&lua, (sha512_manifest.clone(), files, Some("sha512".to_string()))).unwrap();
+        assert!(check_checksums(&lua, (sha512_manifest, None)).unwrap().0);
+
+        fs::write(&manifest, "invalid\n").unwrap();
+        assert!(read_checksums(&lua, manifest).is_err());
+    }
+}
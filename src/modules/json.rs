@@ -1,6 +1,8 @@
-use mlua::{Lua, LuaSerdeExt};
+use mlua::{Lua, LuaSerdeExt, Table};
 use serde_json::Value as JsonValue;
 use std::fs;
+use crate::modules::filesystem::atomic_option;
+use crate::utils::atomic_write;
 use mlua::prelude::{LuaError, LuaValue};
 
 pub(crate) fn load_file(lua: &Lua, path: String) -> mlua::Result<mlua::Value> {
@@ -17,17 +19,17 @@ pub(crate) fn from_string(lua: &Lua, content: String) -> mlua::Result<mlua::Valu
     Ok(lua_value)
 }
 
-pub(crate) fn save_file(lua: &Lua, (path, table): (String, mlua::Value)) -> mlua::Result<()> {
+pub(crate) fn save_file(lua: &Lua, (path, table, options): (String, mlua::Value, Option<Table>)) -> mlua::Result<()> {
     let json: JsonValue = lua.from_value(table)?;
     let content = match serde_json::to_string_pretty(&json) {
         Ok(val) => val,
         Err(e) => return Err(LuaError::RuntimeError(e.to_string())),
     };
-    fs::write(path, content)?;
+    atomic_write::write_file(path, content.as_bytes(), atomic_option(&options)?)?;
     Ok(())
 }
 
-fn json_to_lua(lua: &Lua, value: JsonValue) -> mlua::Result<LuaValue> {
+pub(crate) fn json_to_lua(lua: &Lua, value: JsonValue) -> mlua::Result<LuaValue> {
     match value {
         JsonValue::Null => Ok(LuaValue::Nil),
         JsonValue::Bool(b) => Ok(LuaValue::Boolean(b)),
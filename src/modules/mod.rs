@@ -1,6 +1,9 @@
 pub mod environment;
 pub mod files;
+pub mod file_handle;
 pub mod filesystem;
+pub mod grep;
+pub mod hash;
 pub mod net;
 pub mod os;
 pub mod path;
@@ -8,3 +11,4 @@ pub mod pipeline_exec;
 pub mod toml;
 pub mod json;
 pub mod string;
+pub mod watch;
@@ -1,23 +1,853 @@
-use std::fs::File;
-use std::io;
-use mlua::Lua;
+use std::fs::{File, OpenOptions};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use std::{fs, io, thread};
+use indicatif::ProgressBar;
+use mlua::{Lua, LuaSerdeExt, Table, Value};
 use mlua::prelude::LuaError;
-use reqwest::blocking::Client;
+use once_cell::sync::Lazy;
+use reqwest::blocking::{Client, Response};
+use reqwest::header::{HeaderMap, CONTENT_DISPOSITION, CONTENT_RANGE, ETAG, IF_RANGE, LAST_MODIFIED, RANGE};
+use reqwest::{Method, StatusCode};
+use serde_json::Value as JsonValue;
+use url::Url;
+use crate::modules::hash::{checksum_algorithm, verify_checksum};
+use crate::modules::json::json_to_lua;
+use crate::utils::duration::seconds_option;
+use crate::utils::hash::Hasher;
+use crate::utils::progress::bytes_progress;
 
-pub fn wget(_lua: &Lua, (url, output_file): (String, Option<String>)) -> mlua::Result<String> {
+/// The HTTP client shared by all the requests, so connections are reused.
+static CLIENT: Lazy<Client> = Lazy::new(|| Client::builder()
+    .user_agent(concat!("lush/", env!("CARGO_PKG_VERSION")))
+    .build()
+    .unwrap_or_else(|_| Client::new()));
 
-    let client = Client::new();
-    let mut response = match client.get(&url).send() {
-        Ok(response) => response,
-        Err(e) => return Err(LuaError::external(e)),
+/// Options of `net.wget`.
+struct DownloadOptions {
+    retries: u32,
+    /// Delay before the first retry, doubled for each one
+    backoff: Duration,
+    resume: bool,
+    checksum: Option<String>,
+    timeout: Option<Duration>,
+    progress: bool,
+    output_dir: Option<PathBuf>,
+    overwrite: bool,
+}
+
+impl DownloadOptions {
+    fn from_table(options: Option<Table>) -> mlua::Result<Self> {
+        let mut res = Self {
+            retries: 3,
+            backoff: Duration::from_secs(1),
+            resume: true,
+            checksum: None,
+            timeout: None,
+            progress: true,
+            output_dir: None,
+            overwrite: false,
+        };
+        if let Some(tb) = options {
+            res.retries = tb.get::<Option<u32>>("retries")?.unwrap_or(res.retries);
+            res.backoff = seconds_option(&tb, "backoff")?.unwrap_or(res.backoff);
+            res.resume = tb.get::<Option<bool>>("resume")?.unwrap_or(res.resume);
+            res.checksum = tb.get("checksum")?;
+            // Fails before downloading if the algorithm is not supported
+            if let Some(ref checksum) = res.checksum {
+                Hasher::new(&checksum_algorithm(checksum, None).0)?;
+            }
+            res.timeout = seconds_option(&tb, "timeout")?;
+            res.progress = tb.get::<Option<bool>>("progress")?.unwrap_or(res.progress);
+            res.output_dir = tb.get::<Option<String>>("output_dir")?.map(PathBuf::from);
+            res.overwrite = tb.get::<Option<bool>>("overwrite")?.unwrap_or(res.overwrite);
+        }
+        Ok(res)
+    }
+}
+
+/// A failed download attempt.
+struct AttemptError {
+    message: String,
+    /// Network errors, server errors and rate limiting may succeed later
+    retryable: bool,
+}
+
+impl AttemptError {
+    fn retryable(message: String) -> Self {
+        Self { message, retryable: true }
+    }
+}
+
+/// Downloads a file.
+///
+/// The file is downloaded to `<name>.part` and renamed when complete and verified. Failed attempts are retried,
+/// continuing from the data already received with a Range request when the server supports it. A `.part` file is
+/// only continued for the same URL and, if the server sent an ETag or Last-Modified, the same version of the file.
+/// Responses with an error status fail without writing the file. A progress bar is shown when stderr is a terminal.
+///
+/// # Arguments
+///
+/// * `_lua` - The Lua state (not used in this function).
+/// * `url` - The URL of the file.
+/// * `output_file` - Optional. The name of the downloaded file. By default, the name given by the server in the
+///   `Content-Disposition` header or the last segment of the URL path, without the query string. Can be replaced
+///   by the options.
+/// * `options` - Optional table containing:
+///   - `retries` (integer): Number of retries after network errors, 5xx and 429 responses. Default 3.
+///   - `backoff` (number): Seconds to wait before the first retry, doubled for each retry. Default 1.
+///   - `resume` (bool): Continues a previous download from its `.part` file. Default true.
+///   - `checksum` (string): Expected hash of the file, as in `fs.verify`, E.g. `"sha256:<hex>"`. The download is
+///     removed if it doesn't match, without replacing an existing file.
+///   - `timeout` (number): Timeout of each attempt, in seconds.
+///   - `progress` (bool): Shows a progress bar when stderr is a terminal. Default true.
+///   - `output_dir` (string): The directory of the downloaded file, created if needed.
+///   - `overwrite` (bool): Replaces an existing file. Default false, the download fails if the file exists. A
+///     name given by the server is never replaced, so it can't choose which file of the directory to overwrite.
+///
+/// # Returns
+///
+/// * The path of the downloaded file.
+/// * Returns an error if the download fails.
+///
+/// # Example (in Lua)
+///
+/// ```lua
+/// local file = net.wget("https://example.com/release.tar.gz", { retries = 5, checksum = "sha256:9f86d08..." })
+/// local file = net.wget("https://example.com/download?id=42", { output_dir = "downloads", overwrite = true })
+/// ```
+pub fn wget(_lua: &Lua, (url, output_file, options): (String, Value, Option<Table>)) -> mlua::Result<String> {
+    // The output file can be omitted: net.wget(url, options)
+    let (output_file, options) = match output_file {
+        Value::Table(tb) => (None, Some(tb)),
+        Value::Nil => (None, options),
+        value => (Some(value.to_string()?), options),
+    };
+    let options = DownloadOptions::from_table(options)?;
+    let parsed_url = Url::parse(&url).map_err(|e| LuaError::RuntimeError(format!("Invalid URL {}: {}", url, e)))?;
+
+    let output_dir = options.output_dir.clone().unwrap_or_default();
+    if !output_dir.as_os_str().is_empty() {
+        fs::create_dir_all(&output_dir)?;
+    }
+    let dest = match output_file {
+        Some(ref name) => {
+            let dest = output_dir.join(name);
+            check_overwrite(&dest, options.overwrite).map_err(LuaError::RuntimeError)?;
+            dest
+        }
+        None => output_dir.join(url_filename(&parsed_url)),
+    };
+
+    let mut part_name = dest.as_os_str().to_owned();
+    part_name.push(".part");
+    let part = PathBuf::from(part_name);
+    let disposition_name = download(parsed_url.as_str(), &part, &options).map_err(LuaError::RuntimeError)?;
+
+    // The name given by the server replaces the one from the URL, not the one given by the script. Overwrite only
+    // applies to the names chosen by the script or the URL
+    let (dest, from_server) = match disposition_name {
+        Some(name) if output_file.is_none() && output_dir.join(&name) != dest => (output_dir.join(name), true),
+        _ => (dest, false),
     };
+    if let Some(ref checksum) = options.checksum
+        && let Err(msg) = verify_checksum(&part.to_string_lossy(), checksum, None) {
+        remove_part(&part);
+        return Err(LuaError::RuntimeError(msg));
+    }
+    let checked = match from_server {
+        true if dest.exists() => Err(format!("{} already exists, the name was given by the server", dest.display())),
+        true => Ok(()),
+        false => check_overwrite(&dest, options.overwrite),
+    };
+    if let Err(msg) = checked {
+        remove_part(&part);
+        return Err(LuaError::RuntimeError(msg));
+    }
+    fs::rename(&part, &dest).map_err(|e| LuaError::RuntimeError(format!("{}: {}", dest.display(), e)))?;
+    remove_part(&part);
+
+    Ok(dest.to_string_lossy().to_string())
+}
+
+fn check_overwrite(dest: &Path, overwrite: bool) -> Result<(), String> {
+    if !overwrite && dest.exists() {
+        return Err(format!("{} already exists, use overwrite = true to replace it", dest.display()));
+    }
+    Ok(())
+}
+
+/// The name of the file of a URL, the last non-empty segment of its path. `index.html` if there is none, as for
+/// `https://example.com/`.
+fn url_filename(url: &Url) -> String {
+    url.path_segments()
+        .and_then(|mut segments| segments.rfind(|s| !s.is_empty()))
+        .and_then(|s| safe_filename(&percent_decode(s)))
+        .unwrap_or("index.html".to_string())
+}
+
+/// The file name of a `Content-Disposition` header, E.g. `attachment; filename="release.tar.gz"`. The encoded
+/// `filename*` has precedence.
+fn content_disposition_filename(header: &str) -> Option<String> {
+    let mut filename = None;
+    for param in header.split(';').map(str::trim) {
+        let (name, value) = match param.split_once('=') {
+            Some((name, value)) => (name.trim().to_lowercase(), value.trim()),
+            None => continue,
+        };
+        match name.as_str() {
+            // RFC 5987 encoding: charset'language'percent-encoded-name
+            "filename*" => {
+                if let Some((_, encoded)) = value.split_once('\'').and_then(|(_, rest)| rest.split_once('\'')) {
+                    return safe_filename(&percent_decode(encoded));
+                }
+            }
+            "filename" => filename = safe_filename(value.trim_matches('"')),
+            _ => {}
+        }
+    }
+    filename
+}
+
+/// Keeps only the file name of a name suggested by a server, so it can't write outside the output directory.
+/// Hidden files, like `.bashrc`, are refused.
+fn safe_filename(name: &str) -> Option<String> {
+    let name = name.rsplit(['/', '\\']).next().unwrap_or("").trim();
+    match name {
+        "" => None,
+        _ if name.starts_with('.') => None,
+        _ if name.chars().any(char::is_control) => None,
+        _ => Some(name.to_string()),
+    }
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+/// Downloads `url` to the `part` file, retrying the failed attempts.
+///
+/// # Returns
+///
+/// * The file name of the `Content-Disposition` header, if any.
+fn download(url: &str, part: &Path, options: &DownloadOptions) -> Result<Option<String>, String> {
+    if !options.resume {
+        remove_part(part);
+    }
+
+    let mut delay = options.backoff;
+    let mut attempt = 0;
+    loop {
+        match download_attempt(url, part, options) {
+            Ok(name) => return Ok(name),
+            Err(e) if e.retryable && attempt < options.retries => {
+                thread::sleep(delay);
+                delay *= 2;
+                attempt += 1;
+            }
+            Err(e) => return Err(e.message),
+        }
+    }
+}
+
+/// Downloads `url` to the `part` file, continuing from its current size.
+fn download_attempt(url: &str, part: &Path, options: &DownloadOptions) -> Result<Option<String>, AttemptError> {
+    let mut offset = fs::metadata(part).map(|md| md.len()).unwrap_or(0);
+    let info = read_part_info(part);
+    // A part file of another URL, E.g. download?id=42 and download?id=43, is downloaded again
+    if offset > 0 && info.as_ref().is_none_or(|i| i.url != url) {
+        remove_part(part);
+        offset = 0;
+    }
+
+    let mut builder = CLIENT.get(url);
+    if offset > 0 {
+        builder = builder.header(RANGE, format!("bytes={}-", offset));
+        // The server sends the whole file if it changed since the part file was started
+        if let Some(validator) = info.and_then(|i| i.validator) {
+            builder = builder.header(IF_RANGE, validator);
+        }
+    }
+    if let Some(timeout) = options.timeout {
+        builder = builder.timeout(timeout);
+    }
+
+    let mut response = builder.send().map_err(|e| AttemptError::retryable(error_message(&e)))?;
+    let status = response.status();
+    let disposition_name = response.headers().get(CONTENT_DISPOSITION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(content_disposition_filename);
+    if status == StatusCode::RANGE_NOT_SATISFIABLE && offset > 0 {
+        // Either the file was complete, or the part file is not from this URL
+        let total = response.headers().get(CONTENT_RANGE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("bytes */"))
+            .and_then(|v| v.parse::<u64>().ok());
+        if total == Some(offset) {
+            return Ok(disposition_name);
+        }
+        remove_part(part);
+        return download_attempt(url, part, options);
+    }
+    if !status.is_success() {
+        return Err(AttemptError {
+            message: format!("{}: HTTP {}", url, status),
+            retryable: status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS,
+        });
+    }
+
+    // Servers without Range support, or with a newer version of the file, send the whole file again
+    let resumed = status == StatusCode::PARTIAL_CONTENT;
+    if resumed && content_range_start(response.headers()) != Some(offset) {
+        remove_part(part);
+        if offset > 0 {
+            return download_attempt(url, part, options);
+        }
+        return Err(AttemptError::retryable(format!("{}: unexpected partial content", url)));
+    }
+    let file = match resumed {
+        true => OpenOptions::new().append(true).open(part),
+        false => File::create(part).and_then(|file| {
+            write_part_info(part, url, validator(response.headers()))?;
+            Ok(file)
+        }),
+    };
+    let mut file = file.map_err(|e| AttemptError { message: format!("{}: {}", part.display(), e), retryable: false })?;
+
+    let start = if resumed { offset } else { 0 };
+    let total = response.content_length().map(|len| start + len);
+    let progress = match options.progress {
+        true => bytes_progress(total, url),
+        false => ProgressBar::hidden(),
+    };
+    progress.set_position(start);
+    let copied = io::copy(&mut response, &mut progress.wrap_write(&mut file));
+    progress.finish_and_clear();
+
+    let copied = copied.map_err(|e| AttemptError::retryable(format!("{}: {}", url, e)))?;
+    match total {
+        Some(total) if start + copied < total => Err(AttemptError::retryable(
+            format!("{}: incomplete download, {} of {} bytes", url, start + copied, total))),
+        _ => Ok(disposition_name),
+    }
+}
+
+/// Where a `.part` file was downloaded from, saved in `<name>.part.meta` to continue it only from the same file.
+struct PartInfo {
+    url: String,
+    /// ETag or Last-Modified of the response, sent as If-Range
+    validator: Option<String>,
+}
+
+fn part_info_path(part: &Path) -> PathBuf {
+    let mut name = part.as_os_str().to_owned();
+    name.push(".meta");
+    PathBuf::from(name)
+}
+
+fn read_part_info(part: &Path) -> Option<PartInfo> {
+    let content = fs::read_to_string(part_info_path(part)).ok()?;
+    let mut lines = content.lines();
+    let url = lines.next()?.to_string();
+    let validator = lines.next().filter(|v| !v.is_empty()).map(str::to_string);
+    Some(PartInfo { url, validator })
+}
+
+fn write_part_info(part: &Path, url: &str, validator: Option<String>) -> io::Result<()> {
+    fs::write(part_info_path(part), format!("{}\n{}\n", url, validator.unwrap_or_default()))
+}
+
+/// Removes a part file and its information, ignoring missing files.
+fn remove_part(part: &Path) {
+    let _ = fs::remove_file(part);
+    let _ = fs::remove_file(part_info_path(part));
+}
+
+/// The validator of a response for If-Range: a strong ETag or the Last-Modified date.
+fn validator(headers: &HeaderMap) -> Option<String> {
+    let header = |name| headers.get(name).and_then(|v| v.to_str().ok()).map(str::to_string);
+    header(ETAG).filter(|etag| !etag.starts_with("W/")).or_else(|| header(LAST_MODIFIED))
+}
+
+/// The first byte of a `Content-Range: bytes <start>-<end>/<total>` header.
+fn content_range_start(headers: &HeaderMap) -> Option<u64> {
+    headers.get(CONTENT_RANGE)?.to_str().ok()?
+        .strip_prefix("bytes ")?
+        .split_once('-')?.0
+        .trim().parse().ok()
+}
+
+/// An HTTP request, as described by the table of `net.request`.
+struct HttpRequest {
+    method: Method,
+    url: String,
+    headers: Vec<(String, String)>,
+    query: Vec<(String, String)>,
+    body: Option<Vec<u8>>,
+    json: Option<JsonValue>,
+    timeout: Option<Duration>,
+}
+
+impl HttpRequest {
+    fn new(method: Method, url: String) -> Self {
+        Self { method, url, headers: vec![], query: vec![], body: None, json: None, timeout: None }
+    }
+
+    /// Reads the optional fields of a request: headers, query, body, json and timeout.
+    fn set_options(&mut self, lua: &Lua, options: &Table) -> mlua::Result<()> {
+        self.headers = string_pairs(options.get("headers")?)?;
+        self.query = string_pairs(options.get("query")?)?;
+        if let Some(body) = options.get::<Option<mlua::String>>("body")? {
+            self.body = Some(body.as_bytes().to_vec());
+        }
+        match options.get::<Value>("json")? {
+            Value::Nil => {}
+            value => self.json = Some(lua.from_value(value)?),
+        }
+        self.timeout = seconds_option(options, "timeout")?;
+        Ok(())
+    }
+
+    fn send(self) -> reqwest::Result<Response> {
+        let mut builder = CLIENT.request(self.method, &self.url).query(&self.query);
+        for (name, value) in self.headers.iter() {
+            builder = builder.header(name, value);
+        }
+        if let Some(json) = self.json {
+            if !self.headers.iter().any(|(name, _)| name.eq_ignore_ascii_case("content-type")) {
+                builder = builder.header(reqwest::header::CONTENT_TYPE, "application/json");
+            }
+            builder = builder.body(json.to_string());
+        } else if let Some(body) = self.body {
+            builder = builder.body(body);
+        }
+        if let Some(timeout) = self.timeout {
+            builder = builder.timeout(timeout);
+        }
+        builder.send()
+    }
+}
+
+/// Converts a table `{ name = value }` to a list of pairs. The values are converted to strings.
+fn string_pairs(value: Value) -> mlua::Result<Vec<(String, String)>> {
+    let tb = match value {
+        Value::Nil => return Ok(vec![]),
+        Value::Table(tb) => tb,
+        _ => return Err(LuaError::RuntimeError("headers and query must be tables".to_string())),
+    };
+    let mut pairs = vec![];
+    for pair in tb.pairs::<String, Value>() {
+        let (name, value) = pair?;
+        pairs.push((name, value.to_string()?));
+    }
+    pairs.sort();
+    Ok(pairs)
+}
+
+/// Converts a response to a table `{ status, headers, body, json }`. `json` is the decoded body when the response is
+/// JSON.
+fn response_table(lua: &Lua, response: Response) -> mlua::Result<Table> {
+    let res = lua.create_table()?;
+    res.set("status", response.status().as_u16())?;
+
+    let headers = lua.create_table()?;
+    for name in response.headers().keys() {
+        let values: Vec<String> = response.headers().get_all(name).iter()
+            .map(|v| String::from_utf8_lossy(v.as_bytes()).to_string())
+            .collect();
+        headers.set(name.as_str(), values.join(", "))?;
+    }
+    res.set("headers", headers)?;
+
+    let is_json = response.headers().get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.contains("json"));
+    let body = response.bytes().map_err(LuaError::external)?;
+    if is_json && let Ok(json) = serde_json::from_slice::<JsonValue>(&body) {
+        res.set("json", json_to_lua(lua, json)?)?;
+    }
+    res.set("body", lua.create_string(&body)?)?;
+    Ok(res)
+}
+
+fn send_request(lua: &Lua, request: HttpRequest) -> mlua::Result<(Option<Table>, Option<String>)> {
+    match request.send() {
+        Ok(response) => Ok((Some(response_table(lua, response)?), None)),
+        Err(e) => Ok((None, Some(error_message(&e)))),
+    }
+}
+
+/// The message of a request error, with its cause, E.g. "error sending request for url (...): connection refused".
+fn error_message(e: &reqwest::Error) -> String {
+    let mut msg = e.to_string();
+    let mut source = std::error::Error::source(e);
+    while let Some(cause) = source {
+        msg = format!("{}: {}", msg, cause);
+        source = cause.source();
+    }
+    msg
+}
+
+/// Sends an HTTP request.
+///
+/// Responses with error status, E.g. 404, are returned as any other response. Only failures to get a response
+/// return `nil` and the error message.
+///
+/// # Arguments
+///
+/// * `lua` - The Lua state.
+/// * `options` - A table containing:
+///   - `url` (string): The URL. Required.
+///   - `method` (string): The HTTP method. Default `GET`.
+///   - `headers` (table): The request headers, `{ name = value }`.
+///   - `query` (table): Parameters added to the query string of the URL, `{ name = value }`.
+///   - `body` (string): The request body.
+///   - `json` (table or value): A value sent as JSON, with `Content-Type: application/json`. Replaces `body`.
+///   - `timeout` (number): Timeout of the whole request, in seconds.
+///
+/// # Returns
+///
+/// * A table `{ status, headers, body, json }`. Header names are in lower case. `json` is the decoded body when the
+///   response is JSON.
+/// * `nil` and the error message if the request failed.
+///
+/// # Example (in Lua)
+///
+/// ```lua
+/// local res, err = net.request{ method = "PUT", url = "https://api.example.com/items/1",
+///                               headers = { Authorization = "Bearer " .. token }, json = { name = "lush" } }
+/// if res and res.status == 200 then print(res.body) end
+/// ```
+pub(crate) fn request(lua: &Lua, options: Table) -> mlua::Result<(Option<Table>, Option<String>)> {
+    let url: String = options.get::<Option<String>>("url")?
+        .ok_or_else(|| LuaError::RuntimeError("net.request requires an url".to_string()))?;
+    let method: String = options.get::<Option<String>>("method")?.unwrap_or("GET".to_string());
+    let method = Method::from_bytes(method.to_uppercase().as_bytes())
+        .map_err(|_| LuaError::RuntimeError(format!("Invalid HTTP method {}", method)))?;
+
+    let mut request = HttpRequest::new(method, url);
+    request.set_options(lua, &options)?;
+    send_request(lua, request)
+}
+
+/// Sends a GET request. Same as `net.request` with method `GET`.
+///
+/// # Arguments
+///
+/// * `lua` - The Lua state.
+/// * `url` - The URL.
+/// * `options` - Optional table with the `headers`, `query` and `timeout` of `net.request`.
+///
+/// # Returns
+///
+/// * A table `{ status, headers, body, json }`, or `nil` and the error message.
+///
+/// # Example (in Lua)
+///
+/// ```lua
+/// local res = net.get("https://api.github.com/repos/thiagomg/lush", { headers = { Accept = "application/json" } })
+/// print(res.json.description)
+/// ```
+pub(crate) fn get(lua: &Lua, (url, options): (String, Option<Table>)) -> mlua::Result<(Option<Table>, Option<String>)> {
+    let mut request = HttpRequest::new(Method::GET, url);
+    if let Some(ref tb) = options {
+        request.set_options(lua, tb)?;
+    }
+    send_request(lua, request)
+}
+
+/// Sends a POST request. Same as `net.request` with method `POST`.
+///
+/// # Arguments
+///
+/// * `lua` - The Lua state.
+/// * `url` - The URL.
+/// * `body` - The request body. A string is sent as is, a table is sent as JSON.
+/// * `options` - Optional table with the `headers`, `query` and `timeout` of `net.request`.
+///
+/// # Returns
+///
+/// * A table `{ status, headers, body, json }`, or `nil` and the error message.
+///
+/// # Example (in Lua)
+///
+/// ```lua
+/// local res = net.post("https://api.example.com/items", { name = "lush" })
+/// print(res.status, res.json.id)
+/// ```
+pub(crate) fn post(lua: &Lua, (url, body, options): (String, Value, Option<Table>)) -> mlua::Result<(Option<Table>, Option<String>)> {
+    let mut request = HttpRequest::new(Method::POST, url);
+    if let Some(ref tb) = options {
+        request.set_options(lua, tb)?;
+    }
+    match body {
+        Value::Nil => {}
+        Value::String(s) => request.body = Some(s.as_bytes().to_vec()),
+        value => request.json = Some(lua.from_value(value)?),
+    }
+    send_request(lua, request)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+    use crate::setup;
+
+    /// Serves the responses, one per connection, on a local port. Returns the base URL and a handle returning the
+    /// requests received.
+    fn serve(responses: Vec<String>) -> (String, thread::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let handle = thread::spawn(move || {
+            let mut requests = vec![];
+            for response in responses {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request = String::new();
+                let mut content_length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if let Some(len) = line.to_lowercase().strip_prefix("content-length:") {
+                        content_length = len.trim().parse().unwrap();
+                    }
+                    request.push_str(&line);
+                    if line == "\r\n" || line.is_empty() {
+                        break;
+                    }
+                }
+                let mut body = vec![0; content_length];
+                reader.read_exact(&mut body).unwrap();
+                request.push_str(&String::from_utf8_lossy(&body));
+                requests.push(request);
+                (&stream).write_all(response.as_bytes()).unwrap();
+            }
+            requests
+        });
+        (url, handle)
+    }
+
+    fn http_response(status: &str, headers: &[&str], body: &str) -> String {
+        let mut response = format!("HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n", status, body.len());
+        for header in headers {
+            response.push_str(header);
+            response.push_str("\r\n");
+        }
+        format!("{}\r\n{}", response, body)
+    }
+
+    #[test]
+    fn test_request() {
+        let (url, server) = serve(vec![
+            http_response("200 OK", &["Content-Type: application/json"], r#"{"id": 7, "tags": ["a", "b"]}"#),
+            http_response("201 Created", &["X-Id: 8"], "created"),
+            http_response("404 Not Found", &[], "missing"),
+        ]);
+        let lua = Lua::new();
+        setup::set_utils(&lua).unwrap();
+
+        let script = format!(r#"
+            local url = "{}"
+            local res = net.get(url .. "/items", {{ query = {{ page = 2 }}, headers = {{ Accept = "application/json" }} }})
+            assert(res.status == 200)
+            assert(res.json.id == 7 and res.json.tags[2] == "b")
+            assert(res.headers["content-type"] == "application/json")
+
+            res = net.post(url .. "/items", {{ name = "lush" }})
+            assert(res.status == 201 and res.body == "created" and res.headers["x-id"] == "8")
+
+            res = net.request{{ method = "delete", url = url .. "/items/9", body = "bye", timeout = 5 }}
+            assert(res.status == 404 and res.body == "missing" and res.json == nil)
+
+            local res, err = net.get("http://127.0.0.1:1/")
+            assert(res == nil and err ~= nil)
+            assert(not pcall(net.request, {{ method = "GET" }}))
+            assert(not pcall(net.get, url, {{ timeout = math.huge }}))
+        "#, url);
+        lua.load(script).exec().unwrap();
+
+        let requests = server.join().unwrap();
+        assert!(requests[0].starts_with("GET /items?page=2 HTTP/1.1"));
+        assert!(requests[0].to_lowercase().contains("accept: application/json"));
+        assert!(requests[1].starts_with("POST /items HTTP/1.1"));
+        assert!(requests[1].contains("application/json"));
+        assert!(requests[1].ends_with(r#"{"name":"lush"}"#));
+        assert!(requests[2].starts_with("DELETE /items/9 HTTP/1.1"));
+        assert!(requests[2].ends_with("bye"));
+    }
+
+    #[test]
+    fn test_wget_retries_and_status() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let (url, server) = serve(vec![
+            http_response("503 Service Unavailable", &[], "busy"),
+            http_response("200 OK", &[], "content"),
+            http_response("404 Not Found", &[], "missing"),
+        ]);
+        let lua = Lua::new();
+        setup::set_utils(&lua).unwrap();
+
+        let script = format!(r#"
+            local dir = "{}"
+            local file = net.wget("{}/file.txt", dir .. "/file.txt", {{ backoff = 0 }})
+            assert(fs.read_file(file) == "content")
+            assert(not pcall(net.wget, "{}/missing.txt", dir .. "/missing.txt", {{ backoff = 0 }}))
+        "#, temp_dir.path().display(), url, url);
+        lua.load(script).exec().unwrap();
+        assert_eq!(server.join().unwrap().len(), 3);
+        assert!(!temp_dir.path().join("missing.txt").exists());
+        assert!(!temp_dir.path().join("missing.txt.part").exists());
+    }
+
+    #[test]
+    fn test_wget_resume_and_checksum() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let (url, server) = serve(vec![
+            http_response("206 Partial Content", &["Content-Range: bytes 6-10/11"], "world"),
+            http_response("200 OK", &[], "tampered"),
+            http_response("200 OK", &[], "tampered"),
+        ]);
+        let part = temp_dir.path().join("hello.txt.part");
+        fs::write(&part, "hello ").unwrap();
+        write_part_info(&part, &format!("{}/hello.txt", url), Some("\"v1\"".to_string())).unwrap();
+        let lua = Lua::new();
+        setup::set_utils(&lua).unwrap();
+
+        // sha256 of "hello world"
+        let script = format!(r#"
+            local dir = "{}"
+            local checksum = "sha256:b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9"
+            local file = net.wget("{}/hello.txt", dir .. "/hello.txt", {{ checksum = checksum, progress = false }})
+            assert(fs.read_file(file) == "hello world")
+            local ok, err = pcall(net.wget, "{}/hello.txt", dir .. "/other.txt", {{ checksum = checksum }})
+            assert(not ok and tostring(err):find("mismatch"))
+
+            -- A corrupted download does not replace the existing file
+            ok, err = pcall(net.wget, "{}/hello.txt", file, {{ checksum = checksum, overwrite = true }})
+            assert(not ok and tostring(err):find("mismatch"))
+            assert(fs.read_file(file) == "hello world")
+            assert(not pcall(net.wget, "{}/hello.txt", {{ checksum = "crc:1234" }}))
+            assert(not pcall(net.wget, "{}/hello.txt", {{ timeout = math.huge }}))
+        "#, temp_dir.path().display(), url, url, url, url, url);
+        lua.load(script).exec().unwrap();
+
+        let requests = server.join().unwrap();
+        assert!(requests[0].to_lowercase().contains("range: bytes=6-"));
+        assert!(requests[0].to_lowercase().contains("if-range: \"v1\""));
+        assert!(!temp_dir.path().join("other.txt").exists());
+        assert_eq!(fs::read_dir(temp_dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_wget_invalid_part() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let (url, server) = serve(vec![
+            http_response("200 OK", &["ETag: \"v2\""], "id 43"),
+            http_response("206 Partial Content", &["Content-Range: bytes 0-5/6"], "abcdef"),
+            http_response("200 OK", &[], "abcdef"),
+        ]);
+        let dir = temp_dir.path();
+        // A part file of another URL with the same name
+        fs::write(dir.join("download.part"), "id 42, partial").unwrap();
+        write_part_info(&dir.join("download.part"), &format!("{}/download?id=42", url), None).unwrap();
+        // A part file for which the server returns another range
+        fs::write(dir.join("letters.txt.part"), "abc").unwrap();
+        write_part_info(&dir.join("letters.txt.part"), &format!("{}/letters.txt", url), None).unwrap();
+        let lua = Lua::new();
+        setup::set_utils(&lua).unwrap();
+
+        let script = format!(r#"
+            local dir = "{}"
+            local file = net.wget("{}/download?id=43", {{ output_dir = dir }})
+            assert(fs.read_file(file) == "id 43")
+            file = net.wget("{}/letters.txt", {{ output_dir = dir }})
+            assert(fs.read_file(file) == "abcdef")
+        "#, dir.display(), url, url);
+        lua.load(script).exec().unwrap();
+
+        let requests: Vec<String> = server.join().unwrap().iter().map(|r| r.to_lowercase()).collect();
+        assert!(!requests[0].contains("range:"));
+        assert!(requests[1].contains("range: bytes=3-"));
+        assert!(!requests[2].contains("range:"));
+        assert_eq!(fs::read_dir(dir).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn test_filenames() {
+        let url = |s: &str| Url::parse(s).unwrap();
+        assert_eq!(url_filename(&url("https://example.com/dist/lush-1.0.tar.gz?token=abc")), "lush-1.0.tar.gz");
+        assert_eq!(url_filename(&url("https://example.com/dist/")), "dist");
+        assert_eq!(url_filename(&url("https://example.com/")), "index.html");
+        assert_eq!(url_filename(&url("https://example.com/my%20file.txt")), "my file.txt");
+        assert_eq!(url_filename(&url("https://example.com/a/%2e%2e")), "index.html");
+
+        assert_eq!(content_disposition_filename(r#"attachment; filename="report.pdf""#), Some("report.pdf".to_string()));
+        assert_eq!(content_disposition_filename("attachment; filename=a.txt; filename*=UTF-8''r%C3%A9sum%C3%A9.txt"),
+                   Some("résumé.txt".to_string()));
+        assert_eq!(content_disposition_filename(r#"attachment; filename="../../etc/passwd""#), Some("passwd".to_string()));
+        assert_eq!(content_disposition_filename("inline"), None);
+        assert_eq!(content_disposition_filename(r#"attachment; filename=".bashrc""#), None);
+        assert_eq!(url_filename(&url("https://example.com/.profile")), "index.html");
+    }
+
+    #[test]
+    fn test_wget_output_dir_and_overwrite() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let (url, server) = serve(vec![
+            http_response("200 OK", &[r#"Content-Disposition: attachment; filename="report.csv""#], "a,b"),
+            http_response("200 OK", &[], "first"),
+            http_response("200 OK", &[], "second"),
+            http_response("200 OK", &[], "third"),
+            http_response("200 OK", &[r#"Content-Disposition: attachment; filename="data.txt""#], "evil"),
+            http_response("200 OK", &[r#"Content-Disposition: attachment; filename="report.csv""#], "c,d"),
+        ]);
+        let lua = Lua::new();
+        setup::set_utils(&lua).unwrap();
 
-    let filename = output_file.unwrap_or_else(|| {
-        url.split('/').last().unwrap_or("index.html").to_string()
-    });
+        let script = format!(r#"
+            local dir = "{}/downloads"
+            local file = net.wget("{}/export?format=csv", {{ output_dir = dir }})
+            assert(file == dir .. "/report.csv" and fs.read_file(file) == "a,b")
 
-    let mut file = File::create(&filename)?;
-    io::copy(&mut response, &mut file)?;
+            file = net.wget("{}/data.txt?token=secret", {{ output_dir = dir }})
+            assert(file == dir .. "/data.txt" and fs.read_file(file) == "first")
+            local ok, err = pcall(net.wget, "{}/data.txt", {{ output_dir = dir }})
+            assert(not ok and tostring(err):find("already exists"))
+            net.wget("{}/data.txt", {{ output_dir = dir, overwrite = true }})
+            assert(fs.read_file(file) == "third")
 
-    Ok(filename)
+            -- The server can't pick another existing file to overwrite
+            ok, err = pcall(net.wget, "{}/other.txt", {{ output_dir = dir, overwrite = true }})
+            assert(not ok and tostring(err):find("already exists"))
+            assert(fs.read_file(file) == "third")
+            -- The same name as from the URL can be overwritten
+            file = net.wget("{}/report.csv", {{ output_dir = dir, overwrite = true }})
+            assert(file == dir .. "/report.csv" and fs.read_file(file) == "c,d")
+        "#, temp_dir.path().display(), url, url, url, url, url, url);
+        lua.load(script).exec().unwrap();
+        assert_eq!(server.join().unwrap().len(), 6);
+        assert!(!temp_dir.path().join("downloads/other.txt.part").exists());
+        assert!(!temp_dir.path().join("downloads/data.txt.part").exists());
+    }
 }
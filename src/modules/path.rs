@@ -1,5 +1,9 @@
-use std::path::PathBuf;
+use std::env;
+use std::ffi::OsString;
+use std::path::{Component, Path, PathBuf};
 use mlua::{Lua, Variadic};
+use once_cell::sync::Lazy;
+use regex::Regex;
 
 pub(crate) fn path_join(_lua: &Lua, paths: Variadic<String>) -> mlua::Result<String> {
     let mut full_path = PathBuf::new();
@@ -15,6 +19,233 @@ pub(crate) fn path_join(_lua: &Lua, paths: Variadic<String>) -> mlua::Result<Str
     Ok(str_path.to_string())
 }
 
+/// Returns the last component of a path, or `nil` if the path ends in `..` or is a root.
+///
+/// # Example (in Lua)
+///
+/// ```lua
+/// path.basename("/var/log/syslog.1") -- syslog.1
+/// ```
+pub(crate) fn basename(_lua: &Lua, path: String) -> mlua::Result<Option<String>> {
+    Ok(Path::new(&path).file_name().map(|s| s.to_string_lossy().to_string()))
+}
+
+/// Returns the path without its last component, `""` for a relative path with a single component and `nil` for a
+/// root.
+///
+/// # Example (in Lua)
+///
+/// ```lua
+/// path.dirname("/var/log/syslog.1") -- /var/log
+/// ```
+pub(crate) fn dirname(_lua: &Lua, path: String) -> mlua::Result<Option<String>> {
+    Ok(Path::new(&path).parent().map(path_to_string))
+}
+
+/// Returns the extension of the file name, without the dot.
+///
+/// # Example (in Lua)
+///
+/// ```lua
+/// path.extension("backup.tar.gz") -- gz
+/// ```
+pub(crate) fn extension(_lua: &Lua, path: String) -> mlua::Result<Option<String>> {
+    Ok(Path::new(&path).extension().map(|s| s.to_string_lossy().to_string()))
+}
+
+/// Returns the file name without the extension.
+///
+/// # Example (in Lua)
+///
+/// ```lua
+/// path.stem("/tmp/backup.tar.gz") -- backup.tar
+/// ```
+pub(crate) fn stem(_lua: &Lua, path: String) -> mlua::Result<Option<String>> {
+    Ok(Path::new(&path).file_stem().map(|s| s.to_string_lossy().to_string()))
+}
+
+/// Replaces the extension of the file name, or adds one if there is none. An empty extension removes it.
+/// Fails if the extension contains a path separator.
+///
+/// # Example (in Lua)
+///
+/// ```lua
+/// path.with_extension("/tmp/report.md", "html") -- /tmp/report.html
+/// ```
+pub(crate) fn with_extension(_lua: &Lua, (path, ext): (String, String)) -> mlua::Result<String> {
+    let ext = ext.strip_prefix('.').unwrap_or(&ext);
+    if ext.chars().any(std::path::is_separator) {
+        return Err(mlua::Error::RuntimeError(format!("Invalid extension {}: it contains a path separator", ext)));
+    }
+    Ok(path_to_string(&Path::new(&path).with_extension(ext)))
+}
+
+/// Removes `.` components and resolves `..` without accessing the file system, so symbolic links are not
+/// resolved. Use `fs.realpath` for that.
+///
+/// # Example (in Lua)
+///
+/// ```lua
+/// path.normalize("/opt/app/./bin/../lib/") -- /opt/app/lib
+/// ```
+pub(crate) fn normalize(_lua: &Lua, path: String) -> mlua::Result<String> {
+    Ok(path_to_string(&normalize_path(Path::new(&path))))
+}
+
+/// Returns the absolute and normalized path, relative to the current directory. The path doesn't need to exist.
+///
+/// # Example (in Lua)
+///
+/// ```lua
+/// path.absolute("../logs") -- /home/user/logs, if the current directory is /home/user/app
+/// ```
+pub(crate) fn absolute(_lua: &Lua, path: String) -> mlua::Result<String> {
+    Ok(path_to_string(&absolute_path(Path::new(&path))?))
+}
+
+/// Returns the path of `to` relative to `from`. Both are made absolute first.
+///
+/// # Example (in Lua)
+///
+/// ```lua
+/// path.relative("/opt/app/bin", "/opt/app/lib/libapp.so") -- ../lib/libapp.so
+/// ```
+pub(crate) fn relative(_lua: &Lua, (from, to): (String, String)) -> mlua::Result<String> {
+    let from = absolute_path(Path::new(&from))?;
+    let to = absolute_path(Path::new(&to))?;
+    Ok(path_to_string(&relative_path(&from, &to)))
+}
+
+/// Returns true if the path is absolute, starting with a root like `/`, and false if it is relative to the current
+/// directory.
+///
+/// # Example (in Lua)
+///
+/// ```lua
+/// path.is_absolute("/usr/bin") -- true
+/// ```
+pub(crate) fn is_absolute(_lua: &Lua, path: String) -> mlua::Result<bool> {
+    Ok(Path::new(&path).is_absolute())
+}
+
+/// Splits a path in its components. The root, if any, is the first component.
+///
+/// # Example (in Lua)
+///
+/// ```lua
+/// path.split("/usr/local/bin") -- { "/", "usr", "local", "bin" }
+/// ```
+pub(crate) fn split(_lua: &Lua, path: String) -> mlua::Result<Vec<String>> {
+    let parts = Path::new(&path).components()
+        .map(|c| c.as_os_str().to_string_lossy().to_string())
+        .collect();
+    Ok(parts)
+}
+
+/// Replaces a leading `~` by the home directory of the current user.
+///
+/// # Example (in Lua)
+///
+/// ```lua
+/// path.expand_user("~/.config/lush") -- /home/user/.config/lush
+/// ```
+pub(crate) fn expand_user(_lua: &Lua, path: String) -> mlua::Result<String> {
+    let rest = match path.strip_prefix('~') {
+        Some(rest) if rest.is_empty() || rest.starts_with(['/', '\\']) => rest,
+        _ => return Ok(path),
+    };
+
+    match home_dir() {
+        Some(home) => Ok(format!("{}{}", home.to_string_lossy(), rest)),
+        None => Ok(path),
+    }
+}
+
+fn home_dir() -> Option<OsString> {
+    #[cfg(windows)]
+    let var = "USERPROFILE";
+    #[cfg(not(windows))]
+    let var = "HOME";
+
+    env::var_os(var).filter(|h| !h.is_empty())
+}
+
+static VAR_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"\$(?:\{(\w+)\}|(\w+))").unwrap());
+
+/// Replaces `$NAME` and `${NAME}` by the value of the environment variables. Undefined variables are kept as they
+/// are.
+///
+/// # Example (in Lua)
+///
+/// ```lua
+/// path.expand_vars("$HOME/logs/${APP}.log")
+/// ```
+pub(crate) fn expand_vars(_lua: &Lua, path: String) -> mlua::Result<String> {
+    let res = VAR_RE.replace_all(&path, |caps: &regex::Captures| {
+        let name = caps.get(1).or_else(|| caps.get(2)).unwrap().as_str();
+        env::var(name).unwrap_or_else(|_| caps[0].to_string())
+    });
+    Ok(res.to_string())
+}
+
+fn path_to_string(path: &Path) -> String {
+    path.to_string_lossy().to_string()
+}
+
+pub(crate) fn normalize_path(path: &Path) -> PathBuf {
+    let mut res = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match res.components().next_back() {
+                Some(Component::Normal(_)) => { res.pop(); }
+                // The parent of the root is the root
+                Some(Component::RootDir) | Some(Component::Prefix(_)) => {}
+                _ => res.push(".."),
+            },
+            c => res.push(c.as_os_str()),
+        }
+    }
+
+    if res.as_os_str().is_empty() {
+        res.push(".");
+    }
+    res
+}
+
+pub(crate) fn absolute_path(path: &Path) -> std::io::Result<PathBuf> {
+    if path.is_absolute() {
+        Ok(normalize_path(path))
+    } else {
+        Ok(normalize_path(&env::current_dir()?.join(path)))
+    }
+}
+
+/// Returns `to` relative to `from`. Both paths must be absolute and normalized.
+fn relative_path(from: &Path, to: &Path) -> PathBuf {
+    let from: Vec<Component> = from.components().collect();
+    let to: Vec<Component> = to.components().collect();
+
+    // Different drives on Windows
+    if from.first() != to.first() {
+        return to.iter().collect();
+    }
+
+    let common = from.iter().zip(to.iter()).take_while(|(a, b)| a == b).count();
+    let mut res = PathBuf::new();
+    for _ in common..from.len() {
+        res.push("..");
+    }
+    for c in &to[common..] {
+        res.push(c.as_os_str());
+    }
+
+    if res.as_os_str().is_empty() {
+        res.push(".");
+    }
+    res
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -81,4 +312,60 @@ mod tests {
         #[cfg(windows)]
         assert_eq!(result, "路径\\文件.txt");
     }
+
+    #[test]
+    fn test_components() {
+        let lua = Lua::new();
+        let s = |v: &str| Some(v.to_string());
+        assert_eq!(basename(&lua, "/var/log/syslog.1".to_string()).unwrap(), s("syslog.1"));
+        assert_eq!(basename(&lua, "/".to_string()).unwrap(), None);
+        assert_eq!(dirname(&lua, "/var/log/syslog.1".to_string()).unwrap(), s("/var/log"));
+        assert_eq!(dirname(&lua, "file.txt".to_string()).unwrap(), s(""));
+        assert_eq!(extension(&lua, "backup.tar.gz".to_string()).unwrap(), s("gz"));
+        assert_eq!(extension(&lua, ".bashrc".to_string()).unwrap(), None);
+        assert_eq!(stem(&lua, "/tmp/backup.tar.gz".to_string()).unwrap(), s("backup.tar"));
+        assert_eq!(with_extension(&lua, ("/tmp/report.md".to_string(), ".html".to_string())).unwrap(), "/tmp/report.html");
+        assert_eq!(with_extension(&lua, ("report.md".to_string(), "".to_string())).unwrap(), "report");
+        assert!(with_extension(&lua, ("/tmp/a.txt".to_string(), "x/y".to_string())).is_err());
+        assert_eq!(split(&lua, "/usr/local/bin".to_string()).unwrap(), vec!["/", "usr", "local", "bin"]);
+        assert_eq!(split(&lua, "a/./b".to_string()).unwrap(), vec!["a", "b"]);
+        assert!(is_absolute(&lua, "/usr".to_string()).unwrap());
+        assert!(!is_absolute(&lua, "usr".to_string()).unwrap());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_normalize_and_relative() {
+        let lua = Lua::new();
+        let norm = |p: &str| normalize(&lua, p.to_string()).unwrap();
+        assert_eq!(norm("/opt/app/./bin/../lib/"), "/opt/app/lib");
+        assert_eq!(norm("/../etc"), "/etc");
+        assert_eq!(norm("../a/../../b"), "../../b");
+        assert_eq!(norm("a/.."), ".");
+
+        let rel = |a: &str, b: &str| relative(&lua, (a.to_string(), b.to_string())).unwrap();
+        assert_eq!(rel("/opt/app/bin", "/opt/app/lib/libapp.so"), "../lib/libapp.so");
+        assert_eq!(rel("/opt/app", "/opt/app"), ".");
+        assert_eq!(rel("/opt/app", "/opt/app/bin"), "bin");
+
+        assert_eq!(absolute(&lua, "/a/./b/../c".to_string()).unwrap(), "/a/c");
+        // Other tests change the current directory, so only the shape of the result is checked
+        let abs = absolute(&lua, "x/../y".to_string()).unwrap();
+        assert!(abs.starts_with('/') && abs.ends_with("/y"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_expand() {
+        let lua = Lua::new();
+        let home = env::var("HOME").unwrap();
+        assert_eq!(expand_user(&lua, "~/.config".to_string()).unwrap(), format!("{}/.config", home));
+        assert_eq!(expand_user(&lua, "~".to_string()).unwrap(), home);
+        assert_eq!(expand_user(&lua, "~other/x".to_string()).unwrap(), "~other/x");
+        assert_eq!(expand_user(&lua, "/tmp/~".to_string()).unwrap(), "/tmp/~");
+
+        unsafe { env::set_var("LUSH_PATH_TEST", "lush"); }
+        let res = expand_vars(&lua, "$HOME/${LUSH_PATH_TEST}.log/$LUSH_PATH_UNDEFINED".to_string()).unwrap();
+        assert_eq!(res, format!("{}/lush.log/$LUSH_PATH_UNDEFINED", home));
+    }
 }
@@ -8,6 +8,7 @@ use std::process::{Child, Command, Stdio};
 use std::sync::{mpsc, Arc, Mutex};
 use std::sync::mpsc::{Receiver, Sender};
 use os_pipe::{pipe, PipeReader};
+use crate::setup::LushContext;
 
 struct SingleCommand {
     func_name: String,
@@ -105,7 +106,8 @@ pub fn run_exec(lua: &Lua, value: Variadic<Value>) -> mlua::Result<()> {
     let cmd_table = normalise_table(lua, table)?;
     let cmds = generate_cmds(cmd_table)?;
 
-    let _ = run_piped(cmds, false)?;
+    let (_, status) = run_piped(cmds, false)?;
+    set_last_status(lua, status);
     Ok(())
 }
 
@@ -116,10 +118,18 @@ pub fn run_pipe(lua: &Lua, value: Variadic<Value>) -> mlua::Result<String> {
     let cmd_table = normalise_table(lua, table)?;
     let cmds = generate_cmds(cmd_table)?;
 
-    let res = run_piped(cmds, true)?;
+    let (res, status) = run_piped(cmds, true)?;
+    set_last_status(lua, status);
     Ok(res)
 }
 
+/// Stores the exit status of the last command of a pipeline, so it can be shown in the REPL prompt.
+fn set_last_status(lua: &Lua, status: Option<i32>) {
+    if let Some(mut data) = lua.app_data_mut::<LushContext>() {
+        data.last_status = status;
+    }
+}
+
 fn to_table(lua: &Lua, value: Variadic<Value>) -> mlua::Result<Table> {
     let mut has_table = false;
     for v in value.as_slice() {
@@ -220,7 +230,8 @@ fn generate_cmds(cmd_table: Table) -> mlua::Result<Vec<PipelineStep>> {
     Ok(cmds)
 }
 
-fn run_piped(steps: Vec<PipelineStep>, return_output: bool) -> io::Result<String> {
+/// Runs the pipeline steps, returning the output (if requested) and the exit status of the last external command.
+fn run_piped(steps: Vec<PipelineStep>, return_output: bool) -> io::Result<(String, Option<i32>)> {
     let mut children: Vec<Child> = vec![];
     let mut threads = vec![];
 
@@ -346,15 +357,16 @@ fn run_piped(steps: Vec<PipelineStep>, return_output: bool) -> io::Result<String
             }
         }
 
+        let mut status = None;
         for mut child in children {
-            child.wait()?;
+            status = child.wait()?.code();
         }
 
         for thread in threads {
             thread.join().unwrap();
         }
 
-        Ok("".to_string())
+        Ok(("".to_string(), status))
     } else {
         let mut buffer = Vec::new();
         // Wait for all threads and processes
@@ -362,13 +374,14 @@ fn run_piped(steps: Vec<PipelineStep>, return_output: bool) -> io::Result<String
             thread.join().unwrap();
         }
 
+        let mut status = None;
         for mut child in children {
-            child.wait()?;
+            status = child.wait()?.code();
         }
         if let Some(mut final_output) = input {
             final_output.read_to_end(&mut buffer)?;
         }
-        Ok(String::from_utf8(buffer).unwrap())
+        Ok((String::from_utf8(buffer).unwrap(), status))
     }
 }
 
@@ -521,4 +534,18 @@ mod tests {
         let result = run_pipe(&lua, variadic).unwrap();
         assert_eq!(result.trim(), "3");
     }
+
+    #[test]
+    fn test_last_status() {
+        let lua = Lua::new();
+        lua.set_app_data(LushContext { dir_stack: vec![], last_status: None });
+
+        let values = vec![Value::String(lua.create_string("false").unwrap())];
+        run_pipe(&lua, Variadic::from_iter(values)).unwrap();
+        assert_eq!(lua.app_data_ref::<LushContext>().unwrap().last_status, Some(1));
+
+        let values = vec![Value::String(lua.create_string("true").unwrap())];
+        run_exec(&lua, Variadic::from_iter(values)).unwrap();
+        assert_eq!(lua.app_data_ref::<LushContext>().unwrap().last_status, Some(0));
+    }
 }
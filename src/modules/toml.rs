@@ -1,5 +1,7 @@
 use std::fs;
-use mlua::Lua;
+use mlua::{Lua, Table};
+use crate::modules::filesystem::atomic_option;
+use crate::utils::atomic_write;
 
 pub(crate) fn load_file(lua: &Lua, path: String) -> mlua::Result<mlua::Value> {
     let content = fs::read_to_string(path).map_err(mlua::Error::external)?;
@@ -11,10 +13,10 @@ pub(crate) fn from_string(lua: &Lua, content: String) -> mlua::Result<mlua::Valu
     convert_toml_to_lua(lua, parsed)
 }
 
-pub(crate) fn save_file(_lua: &Lua, (path, table): (String, mlua::Value)) -> mlua::Result<()> {
+pub(crate) fn save_file(_lua: &Lua, (path, table, options): (String, mlua::Value, Option<Table>)) -> mlua::Result<()> {
     let toml_value = convert_lua_to_toml(&table)?;
     let toml_str = toml::to_string_pretty(&toml_value).map_err(mlua::Error::external)?;
-    fs::write(path, toml_str).map_err(mlua::Error::external)?;
+    atomic_write::write_file(path, toml_str.as_bytes(), atomic_option(&options)?).map_err(mlua::Error::external)?;
     
     Ok(())
 }
@@ -0,0 +1,273 @@
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+use mlua::{Function, Lua, Table, Value};
+use notify::event::{ModifyKind, RenameMode};
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use crate::utils::duration::seconds_option;
+
+/// A file system change, after the notify events are debounced.
+#[derive(Debug, PartialEq)]
+struct WatchEvent {
+    kind: &'static str,
+    path: PathBuf,
+    /// The previous path of a renamed file
+    from: Option<PathBuf>,
+}
+
+struct WatchOptions {
+    recursive: bool,
+    debounce: Duration,
+    timeout: Option<Duration>,
+}
+
+impl WatchOptions {
+    fn from_table(options: Option<Table>) -> mlua::Result<Self> {
+        let mut res = Self { recursive: true, debounce: Duration::from_millis(200), timeout: None };
+        if let Some(tb) = options {
+            res.recursive = tb.get::<Option<bool>>("recursive")?.unwrap_or(res.recursive);
+            if let Some(ms) = tb.get::<Option<u64>>("debounce_ms")? {
+                res.debounce = Duration::from_millis(ms);
+            }
+            res.timeout = seconds_option(&tb, "timeout")?;
+        }
+        Ok(res)
+    }
+}
+
+/// Watches files and directories for changes, calling a function for each change. Blocks until the function
+/// returns `false` or the timeout expires.
+///
+/// Events that happen within `debounce_ms` of each other are grouped and repeated changes of the same file are
+/// reported once.
+///
+/// # Arguments
+///
+/// * `lua` - The Lua state.
+/// * `paths` - A path or a table of paths to watch.
+/// * `options` - Optional table containing:
+///   - `recursive` (bool): Watch the subdirectories. Default true.
+///   - `debounce_ms` (integer): Time to wait for more events before calling the function. Default 200.
+///   - `timeout` (number): Stops watching after this number of seconds.
+/// * `callback` - Function receiving a table `{ type, path, from }`, where type is `create`, `modify`, `remove` or
+///   `rename`. `from` is the previous path of a renamed file, when known.
+///
+/// # Example (in Lua)
+///
+/// ```lua
+/// fs.watch("src", { debounce_ms = 500 }, function(event)
+///     print(event.type, event.path)
+///     if event.type == "remove" then
+///         return false -- stop watching
+///     end
+/// end)
+/// ```
+pub(crate) fn watch(lua: &Lua, (paths, options, callback): (Value, Value, Option<Function>)) -> mlua::Result<()> {
+    let paths: Vec<String> = match paths {
+        Value::Table(tb) => tb.sequence_values::<String>().collect::<mlua::Result<_>>()?,
+        value => vec![value.to_string()?],
+    };
+
+    // The options can be omitted: fs.watch(path, function(event) ... end)
+    let (options, callback) = match (options, callback) {
+        (Value::Function(f), None) => (None, f),
+        (Value::Table(tb), Some(f)) => (Some(tb), f),
+        (Value::Nil, Some(f)) => (None, f),
+        _ => return Err(mlua::Error::RuntimeError("fs.watch expects paths, options and a function".to_string())),
+    };
+    let options = WatchOptions::from_table(options)?;
+
+    let (tx, rx) = channel();
+    let mut watcher = notify::recommended_watcher(tx).map_err(mlua::Error::external)?;
+    let mode = if options.recursive { RecursiveMode::Recursive } else { RecursiveMode::NonRecursive };
+    for path in paths.iter() {
+        watcher.watch(Path::new(path), mode)
+            .map_err(|e| mlua::Error::RuntimeError(format!("{}: {}", path, e)))?;
+    }
+
+    // Deadlines too far to be represented wait forever
+    let deadline = options.timeout.and_then(|t| Instant::now().checked_add(t));
+    while let Some(events) = next_events(&rx, options.debounce, deadline)? {
+        for event in events {
+            let event_tb = lua.create_table()?;
+            event_tb.set("type", event.kind)?;
+            event_tb.set("path", event.path.to_string_lossy().to_string())?;
+            event_tb.set("from", event.from.map(|p| p.to_string_lossy().to_string()))?;
+
+            if let Value::Boolean(false) = callback.call::<Value>(event_tb)? {
+                return Ok(());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Waits for the next events and keeps collecting them until no event arrives for `debounce` or the deadline
+/// expires, even if events keep arriving.
+///
+/// # Returns
+///
+/// * The debounced events, or `None` if the deadline expired without events.
+fn next_events(rx: &Receiver<notify::Result<Event>>, debounce: Duration, deadline: Option<Instant>) -> mlua::Result<Option<Vec<WatchEvent>>> {
+    let mut events = vec![];
+    loop {
+        let remaining = deadline.map(|d| d.saturating_duration_since(Instant::now()));
+        if remaining == Some(Duration::ZERO) {
+            return Ok(if events.is_empty() { None } else { Some(events) });
+        }
+        let wait = if events.is_empty() {
+            remaining
+        } else {
+            Some(remaining.map_or(debounce, |r| r.min(debounce)))
+        };
+
+        let received = match wait {
+            Some(wait) => rx.recv_timeout(wait),
+            None => rx.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+
+        match received {
+            Ok(event) => {
+                let event = event.map_err(mlua::Error::external)?;
+                add_event(&mut events, event);
+            }
+            Err(RecvTimeoutError::Timeout) if !events.is_empty() => return Ok(Some(events)),
+            Err(_) => return Ok(None),
+        }
+    }
+}
+
+/// Converts a notify event and adds it to the list, unless the same change of the path is already there.
+///
+/// Some backends, like inotify, report a rename with a remove of the old path, a create of the new one and then
+/// a rename with both paths. Only the rename is kept.
+fn add_event(events: &mut Vec<WatchEvent>, event: Event) {
+    let kind = match event.kind {
+        EventKind::Create(_) | EventKind::Modify(ModifyKind::Name(RenameMode::To)) => "create",
+        EventKind::Remove(_) | EventKind::Modify(ModifyKind::Name(RenameMode::From)) => "remove",
+        EventKind::Modify(ModifyKind::Name(_)) => "rename",
+        EventKind::Modify(_) | EventKind::Any => "modify",
+        EventKind::Access(_) | EventKind::Other => return,
+    };
+
+    let mut paths = event.paths.into_iter();
+    let new_event = match (kind, paths.next(), paths.next()) {
+        ("rename", Some(from), Some(to)) => WatchEvent { kind, path: to, from: Some(from) },
+        (_, Some(path), _) => WatchEvent { kind, path, from: None },
+        _ => return,
+    };
+
+    if let Some(ref from) = new_event.from {
+        events.retain(|e| !(e.kind == "remove" && &e.path == from || e.kind == "create" && e.path == new_event.path));
+    }
+
+    // A new file is usually followed by modify events while it is written
+    let duplicated = events.iter().any(|e| e.path == new_event.path
+        && (e.kind == new_event.kind || (e.kind == "create" && new_event.kind == "modify")));
+    if !duplicated {
+        events.push(new_event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs, thread};
+    use notify::event::{CreateKind, DataChange};
+    use crate::setup;
+
+    #[test]
+    fn test_add_event() {
+        let mut events = vec![];
+        let file = PathBuf::from("/tmp/file.txt");
+        add_event(&mut events, Event::new(EventKind::Create(CreateKind::File)).add_path(file.clone()));
+        add_event(&mut events, Event::new(EventKind::Modify(ModifyKind::Data(DataChange::Content))).add_path(file.clone()));
+        add_event(&mut events, Event::new(EventKind::Modify(ModifyKind::Name(RenameMode::Both)))
+            .add_path(file.clone()).add_path(PathBuf::from("/tmp/new.txt")));
+
+        assert_eq!(events, vec![
+            WatchEvent { kind: "create", path: file.clone(), from: None },
+            WatchEvent { kind: "rename", path: PathBuf::from("/tmp/new.txt"), from: Some(file.clone()) },
+        ]);
+
+        // inotify reports a single mv as From, To and Both
+        let mut events = vec![];
+        let renamed = PathBuf::from("/tmp/renamed.txt");
+        add_event(&mut events, Event::new(EventKind::Modify(ModifyKind::Name(RenameMode::From))).add_path(file.clone()));
+        add_event(&mut events, Event::new(EventKind::Modify(ModifyKind::Name(RenameMode::To))).add_path(renamed.clone()));
+        add_event(&mut events, Event::new(EventKind::Modify(ModifyKind::Name(RenameMode::Both)))
+            .add_path(file.clone()).add_path(renamed.clone()));
+        assert_eq!(events, vec![WatchEvent { kind: "rename", path: renamed, from: Some(file) }]);
+    }
+
+    #[test]
+    fn test_next_events_deadline() {
+        let (tx, rx) = channel();
+        let sender = thread::spawn(move || {
+            // Events keep arriving faster than the debounce time
+            for i in 0..200 {
+                let path = PathBuf::from(format!("/tmp/file{}.txt", i));
+                if tx.send(Ok(Event::new(EventKind::Create(CreateKind::File)).add_path(path))).is_err() {
+                    break;
+                }
+                thread::sleep(Duration::from_millis(10));
+            }
+        });
+
+        let start = Instant::now();
+        let deadline = Some(start + Duration::from_millis(200));
+        let events = next_events(&rx, Duration::from_millis(50), deadline).unwrap().unwrap();
+        assert!(!events.is_empty());
+        assert!(start.elapsed() < Duration::from_millis(1000));
+        assert!(next_events(&rx, Duration::from_millis(50), deadline).unwrap().is_none());
+        drop(rx);
+        sender.join().unwrap();
+    }
+
+    #[test]
+    fn test_watch() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let dir = temp_dir.path().to_path_buf();
+        let lua = Lua::new();
+        setup::set_utils(&lua).unwrap();
+
+        let writer = thread::spawn({
+            let dir = dir.clone();
+            move || {
+                thread::sleep(Duration::from_millis(300));
+                fs::write(dir.join("new.txt"), "content").unwrap();
+            }
+        });
+
+        let script = format!(r#"
+            local events = {{}}
+            fs.watch("{}", {{ debounce_ms = 50, timeout = 10 }}, function(event)
+                table.insert(events, event)
+                return false
+            end)
+            return events
+        "#, dir.display());
+        let events: Table = lua.load(script).eval().unwrap();
+        writer.join().unwrap();
+
+        let first: Table = events.get(1).unwrap();
+        assert_eq!(first.get::<String>("type").unwrap(), "create");
+        assert!(first.get::<String>("path").unwrap().ends_with("new.txt"));
+    }
+
+    #[test]
+    fn test_watch_timeout() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let lua = Lua::new();
+        setup::set_utils(&lua).unwrap();
+
+        let script = format!(r#"fs.watch("{}", {{ timeout = 0.1 }}, function() error("no events") end)"#,
+            temp_dir.path().display());
+        lua.load(script).exec().unwrap();
+
+        assert!(lua.load(r#"fs.watch("/not/a/dir", function() end)"#).exec().is_err());
+        let script = format!(r#"fs.watch("{}", {{ timeout = math.huge }}, function() end)"#, temp_dir.path().display());
+        assert!(lua.load(script).exec().is_err());
+    }
+}
@@ -126,6 +126,15 @@ pub fn replace_sub_shell(text: &str) -> String {
     process_captures(text, re, |arguments: String| format!("os.pipeline({})", arguments))
 }
 
+/// Runs all the pre-processors, in order, over a script.
+///
+/// String interpolation needs to be the last one, as the shell commands generate strings.
+pub fn preprocess(text: &str) -> String {
+    let text = replace_shell_exec(text);
+    let text = replace_sub_shell(&text);
+    interpolate_strings(&text)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1,7 +1,10 @@
+use std::path::PathBuf;
 use mlua::{Lua, Value, Result};
 use rustyline::{ColorMode, Config, Editor};
 use crate::setup;
 use crate::setup::LushContext;
+use crate::repl_commands::*;
+use crate::repl_prompt::build_prompt;
 
 use colored::Colorize;
 use rustyline::history::DefaultHistory;
@@ -11,6 +14,7 @@ pub fn run_repl() -> Result<()> {
     let lua = Lua::new();
     let ctx = LushContext {
         dir_stack: vec![],
+        last_status: None,
     };
     lua.set_app_data(ctx);
     setup::set_utils(&lua)?;
@@ -24,49 +28,33 @@ pub fn run_repl() -> Result<()> {
         .expect("Could not create RL environment");
     rl.set_helper(Some(LushHighlighter));
 
-    println!("{}. Press {} or type `{}` to quit.", "LuSH REPL".bold(), "Ctrl+D".bold(), "exit".bold());
+    println!("{}. Press {} or type `{}` to quit. Type `{}` for help.", "LuSH REPL".bold(), "Ctrl+D".bold(), "exit".bold(), ":help".bold());
 
-    loop {
-        let mut input = String::new();
-        let mut line_count = 0;
-
-        loop {
-            let prompt = if line_count == 0 {
-                "lush> "
-            } else {
-                "....> "
-            };
-
-            let readline = rl.readline(prompt);
-            match readline {
-                Ok(line) => {
-                    let trimmed = line.trim();
-
-                    // Check for exit on first line only
-                    if line_count == 0 && trimmed == "exit" {
-                        return Ok(());
-                    }
+    let mut last_loaded: Option<PathBuf> = None;
 
-                    // Add the line to our input
-                    if line_count > 0 {
-                        input.push('\n');
-                    }
-                    input.push_str(&line);
-                    line_count += 1;
+    loop {
+        // Multi-line input is handled by the validator, so this returns only complete statements
+        let input = match rl.readline(&build_prompt(&lua)) {
+            Ok(line) => line,
+            Err(_) => return Ok(()), // e.g., Ctrl+D
+        };
 
-                    // Check if the input is complete
-                    if is_complete_statement(&input) {
-                        break;
-                    }
-                }
-                Err(_) => return Ok(()), // e.g., Ctrl+D
-            }
+        let trimmed_input = input.trim();
+        if trimmed_input == "exit" {
+            return Ok(());
         }
 
-        let trimmed_input = input.trim();
         if !trimmed_input.is_empty() {
             rl.add_history_entry(trimmed_input).ok();
 
+            if let Some(cmd) = parse_meta_command(trimmed_input) {
+                match cmd {
+                    Ok(cmd) => run_meta_command(&lua, &mut rl, &mut last_loaded, cmd),
+                    Err(msg) => eprintln!("{}", msg.red()),
+                }
+                continue;
+            }
+
             match lua.load(trimmed_input).eval::<Value>() {
                 Ok(Value::Nil) => {
                     if is_valid_lua_identifier(trimmed_input) {
@@ -80,6 +68,55 @@ pub fn run_repl() -> Result<()> {
     }
 }
 
+fn run_meta_command(lua: &Lua, rl: &mut Editor<LushHighlighter, DefaultHistory>, last_loaded: &mut Option<PathBuf>, cmd: MetaCommand) {
+    match cmd {
+        MetaCommand::Load(path) => {
+            // A file that failed to load is not reloaded
+            match load_file(lua, &path) {
+                Ok(_) => *last_loaded = Some(path),
+                Err(err) => print_repl_error(err),
+            }
+        }
+        MetaCommand::Reload(Some(module)) => match reload_module(lua, &module) {
+            Ok(_) => println!("Module {} reloaded", module),
+            Err(err) => print_repl_error(err),
+        },
+        MetaCommand::Reload(None) => match last_loaded {
+            Some(path) => {
+                if let Err(err) = load_file(lua, path) {
+                    print_repl_error(err);
+                }
+            }
+            None => eprintln!("{}", "Nothing to reload. Use :reload <module> or :load <file> first".red()),
+        },
+        MetaCommand::Env(prefix) => {
+            for (key, value) in env_vars(prefix.as_deref()) {
+                println!("{}={}", key.bold(), value);
+            }
+        }
+        MetaCommand::Time(expr) => {
+            let (res, elapsed) = time_expr(lua, &expr);
+            match res {
+                Ok(Value::Nil) => {}
+                Ok(result) => println!("=> {:?}", result),
+                Err(err) => print_repl_error(err),
+            }
+            println!("{} {:?}", "Elapsed:".bold(), elapsed);
+        }
+        MetaCommand::Help(module) => match help_lines(lua, module.as_deref()) {
+            Ok(lines) => print_help(&lines),
+            Err(err) => print_repl_error(err),
+        },
+        MetaCommand::Globals => match global_names(lua) {
+            Ok(names) => println!("{}", names.join(" ")),
+            Err(err) => print_repl_error(err),
+        },
+        MetaCommand::Clear => {
+            rl.clear_screen().ok();
+        }
+    }
+}
+
 fn is_valid_lua_identifier(s: &str) -> bool {
     let reserved = [
         "and", "break", "do", "else", "elseif", "end", "false", "for", "function",
@@ -94,7 +131,7 @@ fn print_repl_error(err: mlua::Error) {
     eprintln!("Error: {}", clean_lua_error(&err.to_string()).red());
 }
 
-fn is_complete_statement(input: &str) -> bool {
+pub(crate) fn is_complete_statement(input: &str) -> bool {
     // Remove string literals and comments to avoid false positives
     let cleaned = remove_strings_and_comments(input);
 
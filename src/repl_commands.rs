@@ -0,0 +1,326 @@
+use std::{env, fs};
+use std::path::PathBuf;
+use std::time::Instant;
+use colored::Colorize;
+use mlua::{Lua, Table, Value};
+use crate::preprocessor::preprocess;
+use crate::string_utils::remove_shebang;
+
+/// Meta-commands understood by the REPL. They start with `:` and are handled before any Lua evaluation.
+#[derive(Debug, PartialEq)]
+pub(crate) enum MetaCommand {
+    Load(PathBuf),
+    Reload(Option<String>),
+    Env(Option<String>),
+    Time(String),
+    Help(Option<String>),
+    Globals,
+    Clear,
+}
+
+const META_COMMANDS: &[(&str, &str)] = &[
+    (":load <file>", "Loads and runs a lua or lush file, using the pre-processors"),
+    (":reload [module]", "Reloads a module loaded with require, or the last file loaded with :load"),
+    (":env [prefix]", "Prints the environment variables, optionally filtered by prefix"),
+    (":time <expr>", "Evaluates an expression and prints how long it took"),
+    (":help [module]", "Prints this help or the functions of a LuSH module"),
+    (":globals", "Lists the global variables"),
+    (":clear", "Clears the screen"),
+];
+
+/// Short description of the functions added by LuSH, used by `:help <module>`.
+const FUNCTION_HELP: &[(&str, &str)] = &[
+    ("env.cd", "Changes the current working directory"),
+    ("env.pushd", "Changes the current directory and pushes the previous one onto the stack"),
+    ("env.popd", "Pops the top directory from the stack and changes to it"),
+    ("env.pwd", "Returns the current working directory"),
+    ("env.cwd", "Returns the current directory or nil if not available"),
+    ("env.get", "Gets the value of an environment variable"),
+    ("env.set", "Sets an environment variable"),
+    ("env.del", "Removes an environment variable"),
+    ("env.print", "Prints values, arrays and formatted strings"),
+    ("fs.ls", "Lists the contents of directories, optionally with metadata, sorted and filtered"),
+    ("fs.mkdir", "Creates a directory and its parents"),
+    ("fs.rmdir", "Removes a directory, optionally recursively"),
+    ("fs.copy", "Copies a file or, with recursive = true, a directory tree"),
+    ("fs.sync", "Copies only the new and changed files of a directory"),
+    ("fs.move", "Moves a file or directory, also across filesystems"),
+    ("fs.rm", "Removes a file, optionally a directory recursively"),
+    ("fs.exists", "Checks if a file or directory exists"),
+    ("fs.is_dir", "Checks if a path is a directory"),
+    ("fs.is_file", "Checks if a path is a file"),
+    ("fs.parent", "Retrieves the parent path of a given path"),
+    ("fs.read_file", "Reads a file and returns its content as string"),
+    ("fs.write_file", "Writes a string to a file"),
+    ("fs.append_file", "Appends a string to a file"),
+    ("fs.open", "Opens a file handle with lines, read, write, append, seek and close"),
+    ("fs.lock", "Acquires an advisory lock on a file"),
+    ("fs.glob", "Returns the paths matching a glob pattern"),
+    ("fs.walk", "Recursively lists the contents of a directory"),
+    ("fs.stat", "Returns the metadata of a file"),
+    ("fs.lstat", "Returns the metadata of a file without following symbolic links"),
+    ("fs.symlink", "Creates a symbolic link"),
+    ("fs.hardlink", "Creates a hard link"),
+    ("fs.readlink", "Returns the target of a symbolic link"),
+    ("fs.realpath", "Returns the absolute path with symbolic links resolved"),
+    ("fs.hash", "Returns the sha256, sha512, sha1, md5 or blake3 hash of a file"),
+    ("fs.verify", "Checks if the hash of a file matches the expected one"),
+    ("fs.same", "Checks if two files have the same content"),
+    ("fs.read_checksums", "Reads a SHA256SUMS style manifest"),
+    ("fs.write_checksums", "Writes a SHA256SUMS style manifest"),
+    ("fs.check_checksums", "Checks the files listed in a SHA256SUMS style manifest"),
+    ("fs.watch", "Calls a function when files are created, modified, removed or renamed"),
+    ("fs.grep", "Searches files for lines matching a regular expression"),
+    ("fs.sed", "Replaces the matches of a regular expression in a file"),
+    ("fs.chmod", "Changes the permissions of a file"),
+    ("fs.chown", "Changes the owner and group of a file"),
+    ("fs.touch", "Creates a file or updates its times"),
+    ("fs.set_mtime", "Sets the modification time of a file"),
+    ("os.name", "Returns the name of the operating system"),
+    ("os.proc_names", "Returns a table { pid: process_name }"),
+    ("os.proc_exes", "Returns a table { pid: process_executable }"),
+    ("os.pipe_exec", "Runs a pipeline of commands printing the output"),
+    ("os.pipeline", "Runs a pipeline of commands returning the output"),
+    ("os.mkdtemp", "Creates a temporary directory removed when the script ends"),
+    ("files.zip", "Adds a list of files to a zip archive"),
+    ("files.unzip", "Decompresses a zip archive"),
+    ("files.compress", "Adds a list of files to a compressed archive"),
+    ("files.decompress", "Decompresses a compressed archive"),
+    ("files.extract", "Extracts an archive, optionally only the entries matching patterns"),
+    ("files.list", "Lists the entries of a zip or tar archive"),
+    ("files.read_entry", "Reads the content of a file in a zip or tar archive"),
+    ("net.wget", "Downloads a file"),
+    ("net.request", "Sends an HTTP request, returning { status, headers, body }"),
+    ("net.get", "Sends an HTTP GET request"),
+    ("net.post", "Sends an HTTP POST request, with a string or a table sent as JSON"),
+    ("toml.load_file", "Loads a toml file into a table"),
+    ("toml.from_string", "Parses a toml string into a table"),
+    ("toml.save_file", "Saves a table as a toml file"),
+    ("json.load_file", "Loads a json file into a table"),
+    ("json.from_string", "Parses a json string into a table"),
+    ("json.save_file", "Saves a table as a json file"),
+    ("path.join", "Joins path components"),
+    ("path.basename", "Returns the last component of a path"),
+    ("path.dirname", "Returns a path without its last component"),
+    ("path.extension", "Returns the extension of a file name"),
+    ("path.stem", "Returns a file name without the extension"),
+    ("path.with_extension", "Replaces the extension of a file name"),
+    ("path.normalize", "Removes . and resolves .. in a path"),
+    ("path.absolute", "Returns the absolute normalized path"),
+    ("path.relative", "Returns a path relative to another one"),
+    ("path.is_absolute", "Checks if a path is absolute"),
+    ("path.split", "Splits a path in its components"),
+    ("path.expand_user", "Replaces a leading ~ by the home directory"),
+    ("path.expand_vars", "Replaces $NAME and ${NAME} by environment variables"),
+    ("string.split", "Splits a string by a separator"),
+    ("string.startswith", "Checks if a string starts with a prefix"),
+    ("string.endswith", "Checks if a string ends with a suffix"),
+    ("string.hash", "Returns the sha256, sha512, sha1, md5 or blake3 hash of a string"),
+];
+
+/// Parses a line starting with `:` into a `MetaCommand`.
+///
+/// # Returns
+///
+/// * `None` if the line is not a meta-command.
+/// * `Some(Err(message))` if the command is unknown or its arguments are invalid.
+pub(crate) fn parse_meta_command(line: &str) -> Option<Result<MetaCommand, String>> {
+    let line = line.trim();
+    if !line.starts_with(':') {
+        return None;
+    }
+
+    let (name, arg) = match line.split_once(char::is_whitespace) {
+        Some((name, arg)) => (name, Some(arg.trim().to_string()).filter(|x| !x.is_empty())),
+        None => (line, None),
+    };
+
+    let cmd = match (name, arg) {
+        (":load", Some(file)) => Ok(MetaCommand::Load(PathBuf::from(file))),
+        (":load", None) => Err("Usage: :load <file>".to_string()),
+        (":reload", module) => Ok(MetaCommand::Reload(module)),
+        (":env", prefix) => Ok(MetaCommand::Env(prefix)),
+        (":time", Some(expr)) => Ok(MetaCommand::Time(expr)),
+        (":time", None) => Err("Usage: :time <expr>".to_string()),
+        (":help", module) => Ok(MetaCommand::Help(module)),
+        (":globals", _) => Ok(MetaCommand::Globals),
+        (":clear", _) => Ok(MetaCommand::Clear),
+        (name, _) => Err(format!("Unknown command {}. Type :help for the list of commands", name)),
+    };
+    Some(cmd)
+}
+
+/// Loads a lua or lush file in the REPL state, running it through the pre-processors.
+pub(crate) fn load_file(lua: &Lua, path: &PathBuf) -> mlua::Result<()> {
+    let script = fs::read_to_string(path)
+        .map_err(|e| mlua::Error::RuntimeError(format!("Error opening {}: {}", path.display(), e)))?;
+    let script = preprocess(&remove_shebang(script));
+    lua.load(script).set_name(path.to_string_lossy()).exec()
+}
+
+/// Removes a module from `package.loaded` and requires it again.
+pub(crate) fn reload_module(lua: &Lua, module: &str) -> mlua::Result<Value> {
+    let package: Table = lua.globals().get("package")?;
+    let loaded: Table = package.get("loaded")?;
+    loaded.set(module, Value::Nil)?;
+
+    let require: mlua::Function = lua.globals().get("require")?;
+    require.call::<Value>(module)
+}
+
+/// Evaluates an expression, returning its result and the time it took.
+pub(crate) fn time_expr(lua: &Lua, expr: &str) -> (mlua::Result<Value>, std::time::Duration) {
+    let start = Instant::now();
+    let res = lua.load(expr).eval::<Value>();
+    (res, start.elapsed())
+}
+
+/// Returns the environment variables, sorted by name, optionally filtered by a prefix.
+pub(crate) fn env_vars(prefix: Option<&str>) -> Vec<(String, String)> {
+    let mut vars: Vec<(String, String)> = env::vars()
+        .filter(|(k, _)| prefix.is_none_or(|p| k.starts_with(p)))
+        .collect();
+    vars.sort();
+    vars
+}
+
+/// Returns the names of the global variables, sorted.
+pub(crate) fn global_names(lua: &Lua) -> mlua::Result<Vec<String>> {
+    let mut names = vec![];
+    for pair in lua.globals().pairs::<Value, Value>() {
+        let (key, _) = pair?;
+        if let Value::String(key) = key {
+            names.push(key.to_str()?.to_string());
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+/// Returns the help lines for a module or, if no module is given, for the meta-commands.
+///
+/// Functions are read from the module table itself, so functions without a description are listed as well.
+pub(crate) fn help_lines(lua: &Lua, module: Option<&str>) -> mlua::Result<Vec<(String, String)>> {
+    let module = match module {
+        None => {
+            return Ok(META_COMMANDS.iter()
+                .map(|(cmd, desc)| (cmd.to_string(), desc.to_string()))
+                .collect());
+        }
+        Some(module) => module,
+    };
+
+    let tb = match lua.globals().get::<Value>(module)? {
+        Value::Table(tb) => tb,
+        _ => return Err(mlua::Error::RuntimeError(format!("Module {} not found", module))),
+    };
+
+    let mut lines = vec![];
+    for pair in tb.pairs::<Value, Value>() {
+        let (key, value) = pair?;
+        if let (Value::String(key), Value::Function(_)) = (key, value) {
+            let name = format!("{}.{}", module, key.to_str()?);
+            let desc = FUNCTION_HELP.iter()
+                .find(|(f, _)| *f == name)
+                .map(|(_, desc)| desc.to_string())
+                .unwrap_or_default();
+            lines.push((name, desc));
+        }
+    }
+    lines.sort();
+    Ok(lines)
+}
+
+pub(crate) fn print_help(lines: &[(String, String)]) {
+    let width = lines.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+    for (name, desc) in lines {
+        println!("  {:width$}  {}", name.bold(), desc, width = width);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::setup;
+    use crate::setup::LushContext;
+
+    fn new_lua() -> Lua {
+        let lua = Lua::new();
+        lua.set_app_data(LushContext { dir_stack: vec![], last_status: None });
+        setup::set_utils(&lua).unwrap();
+        lua
+    }
+
+    #[test]
+    fn test_parse_meta_command() {
+        assert_eq!(parse_meta_command("print(1)"), None);
+        assert_eq!(parse_meta_command(":load test.lush"), Some(Ok(MetaCommand::Load(PathBuf::from("test.lush")))));
+        assert_eq!(parse_meta_command("  :reload  "), Some(Ok(MetaCommand::Reload(None))));
+        assert_eq!(parse_meta_command(":reload mod1"), Some(Ok(MetaCommand::Reload(Some("mod1".to_string())))));
+        assert_eq!(parse_meta_command(":env HOME"), Some(Ok(MetaCommand::Env(Some("HOME".to_string())))));
+        assert_eq!(parse_meta_command(":time 1 + 1"), Some(Ok(MetaCommand::Time("1 + 1".to_string()))));
+        assert_eq!(parse_meta_command(":help fs"), Some(Ok(MetaCommand::Help(Some("fs".to_string())))));
+        assert_eq!(parse_meta_command(":globals"), Some(Ok(MetaCommand::Globals)));
+        assert_eq!(parse_meta_command(":clear"), Some(Ok(MetaCommand::Clear)));
+
+        assert!(matches!(parse_meta_command(":load"), Some(Err(_))));
+        assert!(matches!(parse_meta_command(":time"), Some(Err(_))));
+        assert!(matches!(parse_meta_command(":unknown"), Some(Err(_))));
+    }
+
+    #[test]
+    fn test_load_file_uses_preprocessor() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let file = temp_dir.path().join("load.lush");
+        fs::write(&file, "#!/usr/bin/env lush\nlocal name = 'lush'\nloaded = \"hello ${name}\"\n").unwrap();
+
+        let lua = new_lua();
+        load_file(&lua, &file).unwrap();
+        let loaded: String = lua.globals().get("loaded").unwrap();
+        assert_eq!(loaded, "hello lush");
+
+        assert!(load_file(&lua, &temp_dir.path().join("missing.lush")).is_err());
+    }
+
+    #[test]
+    fn test_reload_module() {
+        let lua = new_lua();
+        lua.load(r#"
+            counter = 0
+            package.preload["counter"] = function() counter = counter + 1; return counter end
+            require("counter")
+        "#).exec().unwrap();
+
+        let res = reload_module(&lua, "counter").unwrap();
+        assert_eq!(res.as_integer(), Some(2));
+    }
+
+    #[test]
+    fn test_time_expr() {
+        let lua = new_lua();
+        let (res, _elapsed) = time_expr(&lua, "1 + 2");
+        assert_eq!(res.unwrap().as_integer(), Some(3));
+    }
+
+    #[test]
+    fn test_env_vars() {
+        unsafe { env::set_var("LUSH_REPL_TEST_VAR", "1"); }
+        let vars = env_vars(Some("LUSH_REPL_TEST_"));
+        assert_eq!(vars, vec![("LUSH_REPL_TEST_VAR".to_string(), "1".to_string())]);
+    }
+
+    #[test]
+    fn test_globals_and_help() {
+        let lua = new_lua();
+        let names = global_names(&lua).unwrap();
+        assert!(names.contains(&"fs".to_string()));
+        assert!(names.contains(&"env".to_string()));
+
+        let lines = help_lines(&lua, Some("fs")).unwrap();
+        let ls = lines.iter().find(|(name, _)| name == "fs.ls").unwrap();
+        assert_eq!(ls.1, "Lists the contents of directories, optionally with metadata, sorted and filtered");
+
+        assert_eq!(help_lines(&lua, None).unwrap().len(), META_COMMANDS.len());
+        assert!(help_lines(&lua, Some("not_a_module")).is_err());
+    }
+}
@@ -0,0 +1,149 @@
+use std::{env, fs};
+use std::path::{Path, PathBuf};
+use colored::Colorize;
+use mlua::{Lua, Table, Value};
+use crate::setup::LushContext;
+
+pub(crate) const DEFAULT_PROMPT: &str = "lush> ";
+
+/// Builds the REPL prompt.
+///
+/// If `lush.prompt` is a function, it is called with a table containing `cwd`, `dir_depth` (size of the
+/// `env.pushd` stack), `git_branch` and `last_status` (exit status of the last pipeline) and its result is used
+/// as prompt. Otherwise, or if the function fails, the default prompt is used.
+///
+/// # Example (in Lua)
+///
+/// ```lua
+/// lush.prompt = function(info)
+///     return info.cwd .. " (" .. tostring(info.git_branch) .. ") > "
+/// end
+/// ```
+pub(crate) fn build_prompt(lua: &Lua) -> String {
+    match call_prompt_function(lua) {
+        Ok(Some(prompt)) => prompt,
+        Ok(None) => DEFAULT_PROMPT.to_string(),
+        Err(err) => {
+            eprintln!("Error: lush.prompt failed: {}", err.to_string().red());
+            DEFAULT_PROMPT.to_string()
+        }
+    }
+}
+
+fn call_prompt_function(lua: &Lua) -> mlua::Result<Option<String>> {
+    let lush_tb: Table = match lua.globals().get::<Value>("lush")? {
+        Value::Table(tb) => tb,
+        _ => return Ok(None),
+    };
+    let prompt_fn = match lush_tb.get::<Value>("prompt")? {
+        Value::Function(f) => f,
+        Value::String(s) => return Ok(Some(s.to_str()?.to_string())),
+        _ => return Ok(None),
+    };
+
+    let info = prompt_info(lua)?;
+    let prompt = prompt_fn.call::<Value>(info)?;
+    match prompt {
+        Value::Nil => Ok(None),
+        prompt => Ok(Some(prompt.to_string()?)),
+    }
+}
+
+/// Creates the table passed to the `lush.prompt` function.
+fn prompt_info(lua: &Lua) -> mlua::Result<Table> {
+    let info = lua.create_table()?;
+    let cwd = env::current_dir().ok();
+    if let Some(ref cwd) = cwd {
+        info.set("cwd", cwd.to_str().unwrap_or(""))?;
+        info.set("git_branch", git_branch(cwd))?;
+    }
+
+    if let Some(data) = lua.app_data_ref::<LushContext>() {
+        info.set("dir_depth", data.dir_stack.len())?;
+        info.set("last_status", data.last_status)?;
+    }
+
+    Ok(info)
+}
+
+/// Returns the current git branch of the repository containing `dir`, reading `.git/HEAD` directly.
+///
+/// If the HEAD is detached, the abbreviated commit hash is returned instead.
+pub(crate) fn git_branch(dir: &Path) -> Option<String> {
+    let git_dir = find_git_dir(dir)?;
+    let head = fs::read_to_string(git_dir.join("HEAD")).ok()?;
+    let head = head.trim();
+
+    match head.strip_prefix("ref: ") {
+        Some(reference) => Some(reference.strip_prefix("refs/heads/").unwrap_or(reference).to_string()),
+        None => Some(head.chars().take(7).collect()),
+    }
+}
+
+fn find_git_dir(dir: &Path) -> Option<PathBuf> {
+    for ancestor in dir.ancestors() {
+        let git_path = ancestor.join(".git");
+        if git_path.is_dir() {
+            return Some(git_path);
+        }
+        // Worktrees and submodules have a .git file pointing to the real git dir
+        if git_path.is_file() {
+            let content = fs::read_to_string(&git_path).ok()?;
+            let git_dir = PathBuf::from(content.trim().strip_prefix("gitdir: ")?);
+            return Some(ancestor.join(git_dir));
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::setup;
+    use tempfile::tempdir;
+
+    fn new_lua() -> Lua {
+        let lua = Lua::new();
+        lua.set_app_data(LushContext { dir_stack: vec![PathBuf::from("/")], last_status: Some(3) });
+        setup::set_utils(&lua).unwrap();
+        lua
+    }
+
+    #[test]
+    fn test_default_prompt() {
+        let lua = new_lua();
+        assert_eq!(build_prompt(&lua), DEFAULT_PROMPT);
+    }
+
+    #[test]
+    fn test_prompt_function() {
+        let lua = new_lua();
+        lua.load(r#"
+            lush.prompt = function(info)
+                return info.dir_depth .. ":" .. info.last_status .. "> "
+            end
+        "#).exec().unwrap();
+        assert_eq!(build_prompt(&lua), "1:3> ");
+    }
+
+    #[test]
+    fn test_prompt_function_error() {
+        let lua = new_lua();
+        lua.load("lush.prompt = function() error('failed') end").exec().unwrap();
+        assert_eq!(build_prompt(&lua), DEFAULT_PROMPT);
+    }
+
+    #[test]
+    fn test_git_branch() {
+        let temp_dir = tempdir().unwrap();
+        let sub_dir = temp_dir.path().join("src");
+        fs::create_dir_all(temp_dir.path().join(".git")).unwrap();
+        fs::create_dir_all(&sub_dir).unwrap();
+
+        fs::write(temp_dir.path().join(".git/HEAD"), "ref: refs/heads/feature/prompt\n").unwrap();
+        assert_eq!(git_branch(&sub_dir), Some("feature/prompt".to_string()));
+
+        fs::write(temp_dir.path().join(".git/HEAD"), "0123456789abcdef\n").unwrap();
+        assert_eq!(git_branch(&sub_dir), Some("0123456".to_string()));
+    }
+}
@@ -3,22 +3,28 @@ use mlua::Lua;
 use mlua::prelude::LuaResult;
 use crate::modules::pipeline_exec::*;
 use crate::modules::files::*;
+use crate::modules::file_handle::{lock, open};
 use crate::modules::environment::*;
 use crate::modules::filesystem::*;
+use crate::modules::grep::{grep, sed};
+use crate::modules::hash::{check_checksums, hash_file, hash_string, read_checksums, verify, write_checksums};
 use crate::modules::net::*;
 use crate::modules::os::*;
-use crate::modules::path::*;
+use crate::modules::path::{self, path_join};
 use crate::modules::string::{endswith, split, startswith};
+use crate::modules::watch::watch;
 use crate::modules::toml::load_file as load_toml;
 use crate::modules::toml::from_string as from_string_toml;
 use crate::modules::toml::save_file as save_toml;
 use crate::modules::json::load_file as load_json;
 use crate::modules::json::from_string as from_string_json;
 use crate::modules::json::save_file as save_json;
-use crate::preprocessor::{interpolate_strings, replace_shell_exec, replace_sub_shell};
+use crate::preprocessor::preprocess;
 
 pub(crate) struct LushContext {
     pub dir_stack: Vec<PathBuf>,
+    /// Exit status of the last command run by `os.pipe_exec` or `os.pipeline`
+    pub last_status: Option<i32>,
 }
 
 pub(crate) fn set_utils(lua: &Lua) -> LuaResult<()> {
@@ -41,6 +47,7 @@ pub(crate) fn set_utils(lua: &Lua) -> LuaResult<()> {
     filesystem_tb.set("mkdir", lua.create_function(mkdir)?)?;
     filesystem_tb.set("rmdir", lua.create_function(rmdir)?)?;
     filesystem_tb.set("copy", lua.create_function(copy_file)?)?;
+    filesystem_tb.set("sync", lua.create_function(sync)?)?;
     filesystem_tb.set("move", lua.create_function(move_file)?)?;
     filesystem_tb.set("rm", lua.create_function(delete_file)?)?;
     filesystem_tb.set("exists", lua.create_function(file_exists)?)?;
@@ -49,6 +56,32 @@ pub(crate) fn set_utils(lua: &Lua) -> LuaResult<()> {
     filesystem_tb.set("parent", lua.create_function(parent)?)?;
     filesystem_tb.set("read_file", lua.create_function(read_file)?)?;
     filesystem_tb.set("write_file", lua.create_function(write_file)?)?;
+    filesystem_tb.set("append_file", lua.create_function(append_file)?)?;
+    filesystem_tb.set("open", lua.create_function(open)?)?;
+    filesystem_tb.set("lock", lua.create_function(lock)?)?;
+    filesystem_tb.set("glob", lua.create_function(glob)?)?;
+    filesystem_tb.set("walk", lua.create_function(walk)?)?;
+    filesystem_tb.set("stat", lua.create_function(stat)?)?;
+    filesystem_tb.set("lstat", lua.create_function(lstat)?)?;
+    filesystem_tb.set("symlink", lua.create_function(symlink)?)?;
+    filesystem_tb.set("hardlink", lua.create_function(hardlink)?)?;
+    filesystem_tb.set("readlink", lua.create_function(readlink)?)?;
+    filesystem_tb.set("realpath", lua.create_function(realpath)?)?;
+    filesystem_tb.set("hash", lua.create_function(hash_file)?)?;
+    filesystem_tb.set("verify", lua.create_function(verify)?)?;
+    filesystem_tb.set("same", lua.create_function(same)?)?;
+    filesystem_tb.set("read_checksums", lua.create_function(read_checksums)?)?;
+    filesystem_tb.set("write_checksums", lua.create_function(write_checksums)?)?;
+    filesystem_tb.set("check_checksums", lua.create_function(check_checksums)?)?;
+    filesystem_tb.set("watch", lua.create_function(watch)?)?;
+    filesystem_tb.set("grep", lua.create_function(grep)?)?;
+    filesystem_tb.set("sed", lua.create_function(sed)?)?;
+    #[cfg(unix)]
+    filesystem_tb.set("chmod", lua.create_function(chmod)?)?;
+    #[cfg(unix)]
+    filesystem_tb.set("chown", lua.create_function(chown)?)?;
+    filesystem_tb.set("touch", lua.create_function(touch)?)?;
+    filesystem_tb.set("set_mtime", lua.create_function(set_mtime)?)?;
     lua.globals().set("fs", filesystem_tb)?;
 
     // Operating System
@@ -66,10 +99,16 @@ pub(crate) fn set_utils(lua: &Lua) -> LuaResult<()> {
     files_tb.set("unzip", lua.create_function(extract_zip)?)?;
     files_tb.set("compress", lua.create_function(compress)?)?;
     files_tb.set("decompress", lua.create_function(decompress)?)?;
+    files_tb.set("extract", lua.create_function(extract)?)?;
+    files_tb.set("list", lua.create_function(list)?)?;
+    files_tb.set("read_entry", lua.create_function(read_entry)?)?;
     lua.globals().set("files", files_tb)?;
 
     let net_tb = lua.create_table()?;
     net_tb.set("wget", lua.create_function(wget)?)?;
+    net_tb.set("request", lua.create_function(request)?)?;
+    net_tb.set("get", lua.create_function(get)?)?;
+    net_tb.set("post", lua.create_function(post)?)?;
     lua.globals().set("net", net_tb)?;
 
     let toml_tb = lua.create_table()?;
@@ -86,12 +125,30 @@ pub(crate) fn set_utils(lua: &Lua) -> LuaResult<()> {
 
     let path_tb = lua.create_table()?;
     path_tb.set("join", lua.create_function(path_join)?)?;
+    path_tb.set("basename", lua.create_function(path::basename)?)?;
+    path_tb.set("dirname", lua.create_function(path::dirname)?)?;
+    path_tb.set("extension", lua.create_function(path::extension)?)?;
+    path_tb.set("stem", lua.create_function(path::stem)?)?;
+    path_tb.set("with_extension", lua.create_function(path::with_extension)?)?;
+    path_tb.set("normalize", lua.create_function(path::normalize)?)?;
+    path_tb.set("absolute", lua.create_function(path::absolute)?)?;
+    path_tb.set("relative", lua.create_function(path::relative)?)?;
+    path_tb.set("is_absolute", lua.create_function(path::is_absolute)?)?;
+    path_tb.set("split", lua.create_function(path::split)?)?;
+    path_tb.set("expand_user", lua.create_function(path::expand_user)?)?;
+    path_tb.set("expand_vars", lua.create_function(path::expand_vars)?)?;
     lua.globals().set("path", path_tb)?;
 
+    // LuSH settings, E.g. lush.prompt
+    let lush_tb = lua.create_table()?;
+    lush_tb.set("version", env!("CARGO_PKG_VERSION"))?;
+    lua.globals().set("lush", lush_tb)?;
+
     let string_tb: mlua::Table = lua.globals().get("string")?;
     string_tb.set("split", lua.create_function(split)?)?;
     string_tb.set("startswith", lua.create_function(startswith)?)?;
     string_tb.set("endswith", lua.create_function(endswith)?)?;
+    string_tb.set("hash", lua.create_function(hash_string)?)?;
     lua.globals().set("string", string_tb)?;
 
     Ok(())
@@ -101,6 +158,7 @@ pub(crate) fn run_script(script: &str, input_file: PathBuf, args: Vec<String>) -
     let lua = Lua::new();
     let ctx = LushContext {
         dir_stack: vec![],
+        last_status: None,
     };
     lua.set_app_data(ctx);
     set_utils(&lua)?;
@@ -120,9 +178,7 @@ pub(crate) fn run_script(script: &str, input_file: PathBuf, args: Vec<String>) -
     lua.load(&add_path).exec()?;
 
     // Before loading the script, let's run through pre-processors
-    let script = replace_shell_exec(&script);
-    let script = replace_sub_shell(&script);
-    let script = interpolate_strings(&script);
+    let script = preprocess(script);
 
     lua.load(script).set_name(script_file_name).exec()
 }
@@ -0,0 +1,141 @@
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use tempfile::Builder;
+
+/// Writes a file atomically.
+///
+/// The content is written to a temporary file in the same directory, flushed to disk and then renamed over the
+/// target, so readers see either the old or the new content, never a truncated file. If the target already
+/// exists, its permissions are kept, otherwise the file gets the default permissions of new files, as with
+/// `fs::write`. A symbolic link is kept and the file it points to is replaced.
+pub fn write_atomic<P: AsRef<Path>>(path: P, data: &[u8]) -> io::Result<()> {
+    let path = resolve_symlinks(path.as_ref())?;
+    let dir = match path.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p,
+        _ => Path::new("."),
+    };
+
+    let mut builder = Builder::new();
+    // Created with 0666 like fs::write, the kernel applies the umask. Temporary files are 0600 by default
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        builder.permissions(fs::Permissions::from_mode(0o666));
+    }
+    let mut tmp_file = builder.tempfile_in(dir)?;
+    tmp_file.write_all(data)?;
+    if let Ok(md) = fs::metadata(&path) {
+        fs::set_permissions(tmp_file.path(), md.permissions())?;
+    }
+    tmp_file.as_file().sync_all()?;
+    tmp_file.persist(&path).map_err(|e| e.error)?;
+
+    // Makes the rename durable
+    #[cfg(unix)]
+    fs::File::open(dir)?.sync_all()?;
+
+    Ok(())
+}
+
+/// Follows symbolic links to the file they point to, which may not exist yet.
+fn resolve_symlinks(path: &Path) -> io::Result<PathBuf> {
+    let mut path = path.to_path_buf();
+    // The same limit as Linux, to stop at link loops
+    for _ in 0..40 {
+        match fs::symlink_metadata(&path) {
+            Ok(md) if md.file_type().is_symlink() => {
+                let target = fs::read_link(&path)?;
+                path = match path.parent() {
+                    Some(parent) => parent.join(target),
+                    None => target,
+                };
+            }
+            _ => return Ok(path),
+        }
+    }
+    Err(io::Error::other(format!("Too many levels of symbolic links: {}", path.display())))
+}
+
+/// Writes a file atomically if `atomic` is set, otherwise with a regular `fs::write`.
+pub fn write_file<P: AsRef<Path>>(path: P, data: &[u8], atomic: bool) -> io::Result<()> {
+    if atomic {
+        write_atomic(path, data)
+    } else {
+        fs::write(path, data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_write_atomic() {
+        let temp_dir = tempdir().unwrap();
+        let file = temp_dir.path().join("config.json");
+
+        write_atomic(&file, b"first").unwrap();
+        assert_eq!(fs::read_to_string(&file).unwrap(), "first");
+
+        // New files get the same permissions as with fs::write
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let plain = temp_dir.path().join("plain.json");
+            fs::write(&plain, b"plain").unwrap();
+            let mode = fs::metadata(&plain).unwrap().permissions().mode();
+            assert_eq!(fs::metadata(&file).unwrap().permissions().mode(), mode);
+            fs::remove_file(&plain).unwrap();
+        }
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&file, fs::Permissions::from_mode(0o600)).unwrap();
+        }
+
+        write_atomic(&file, b"second").unwrap();
+        assert_eq!(fs::read_to_string(&file).unwrap(), "second");
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(fs::metadata(&file).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+
+        // No temporary files left behind
+        assert_eq!(fs::read_dir(temp_dir.path()).unwrap().count(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_atomic_symlink() {
+        let temp_dir = tempdir().unwrap();
+        let real_dir = temp_dir.path().join("real");
+        fs::create_dir(&real_dir).unwrap();
+        let target = real_dir.join("config.json");
+        fs::write(&target, b"old").unwrap();
+        let link = temp_dir.path().join("link.json");
+        std::os::unix::fs::symlink("real/config.json", &link).unwrap();
+
+        write_atomic(&link, b"new").unwrap();
+        assert!(fs::symlink_metadata(&link).unwrap().file_type().is_symlink());
+        assert_eq!(fs::read_to_string(&target).unwrap(), "new");
+        // The temporary file was next to the real file
+        assert_eq!(fs::read_dir(&real_dir).unwrap().count(), 1);
+
+        // A dangling link creates its target
+        let dangling = temp_dir.path().join("dangling.json");
+        std::os::unix::fs::symlink("real/created.json", &dangling).unwrap();
+        write_atomic(&dangling, b"created").unwrap();
+        assert_eq!(fs::read_to_string(real_dir.join("created.json")).unwrap(), "created");
+
+        // Link loops fail
+        let looped = temp_dir.path().join("loop");
+        std::os::unix::fs::symlink("loop", &looped).unwrap();
+        assert!(write_atomic(&looped, b"x").is_err());
+    }
+}
@@ -0,0 +1,39 @@
+use std::time::Duration;
+use mlua::Table;
+
+/// Reads an option given in seconds, E.g. `{ timeout = 2.5 }`. Negative values are 0.
+///
+/// Fails for values that are not finite, like `math.huge`, which don't fit in a `Duration`.
+pub fn seconds_option(tb: &Table, key: &str) -> mlua::Result<Option<Duration>> {
+    match tb.get::<Option<f64>>(key)? {
+        Some(secs) if !secs.is_finite() => Err(mlua::Error::RuntimeError(
+            format!("Invalid {}: {}, expected a finite number of seconds", key, secs))),
+        Some(secs) => Ok(Some(Duration::try_from_secs_f64(secs.max(0.0)).map_err(|e| mlua::Error::RuntimeError(
+            format!("Invalid {}: {}", key, e)))?)),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mlua::Lua;
+
+    #[test]
+    fn test_seconds_option() {
+        let lua = Lua::new();
+        let tb = lua.create_table().unwrap();
+        tb.set("timeout", 1.5).unwrap();
+        tb.set("negative", -3).unwrap();
+        tb.set("huge", f64::INFINITY).unwrap();
+        tb.set("nan", f64::NAN).unwrap();
+        tb.set("too_big", 1e30).unwrap();
+
+        assert_eq!(seconds_option(&tb, "timeout").unwrap(), Some(Duration::from_millis(1500)));
+        assert_eq!(seconds_option(&tb, "negative").unwrap(), Some(Duration::ZERO));
+        assert_eq!(seconds_option(&tb, "missing").unwrap(), None);
+        assert!(seconds_option(&tb, "huge").is_err());
+        assert!(seconds_option(&tb, "nan").is_err());
+        assert!(seconds_option(&tb, "too_big").is_err());
+    }
+}
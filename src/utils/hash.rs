@@ -0,0 +1,121 @@
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::path::Path;
+use md5::Md5;
+use sha1::Sha1;
+use sha2::{Digest, Sha256, Sha512};
+
+/// Hash algorithms supported by `fs.hash` and `string.hash`.
+pub enum Hasher {
+    Sha256(Sha256),
+    Sha512(Sha512),
+    Sha1(Sha1),
+    Md5(Md5),
+    Blake3(Box<blake3::Hasher>),
+}
+
+impl Hasher {
+    pub const ALGORITHMS: [&'static str; 5] = ["sha256", "sha512", "sha1", "md5", "blake3"];
+
+    /// Creates a hasher from the algorithm name, case-insensitive.
+    pub fn new(algorithm: &str) -> io::Result<Self> {
+        match algorithm.to_lowercase().as_str() {
+            "sha256" => Ok(Hasher::Sha256(Sha256::new())),
+            "sha512" => Ok(Hasher::Sha512(Sha512::new())),
+            "sha1" => Ok(Hasher::Sha1(Sha1::new())),
+            "md5" => Ok(Hasher::Md5(Md5::new())),
+            "blake3" => Ok(Hasher::Blake3(Box::new(blake3::Hasher::new()))),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidInput,
+                format!("Unknown hash algorithm {}. Supported: {}", algorithm, Self::ALGORITHMS.join(", ")))),
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Sha256(h) => h.update(data),
+            Hasher::Sha512(h) => h.update(data),
+            Hasher::Sha1(h) => h.update(data),
+            Hasher::Md5(h) => h.update(data),
+            Hasher::Blake3(h) => { h.update(data); }
+        }
+    }
+
+    /// Returns the digest as a lowercase hex string.
+    pub fn finalize_hex(self) -> String {
+        match self {
+            Hasher::Sha256(h) => hex::encode(h.finalize()),
+            Hasher::Sha512(h) => hex::encode(h.finalize()),
+            Hasher::Sha1(h) => hex::encode(h.finalize()),
+            Hasher::Md5(h) => hex::encode(h.finalize()),
+            Hasher::Blake3(h) => h.finalize().to_hex().to_string(),
+        }
+    }
+}
+
+/// Hashes everything read from `reader`, without loading it all in memory.
+pub fn hash_reader<R: Read>(mut reader: R, algorithm: &str) -> io::Result<String> {
+    let mut hasher = Hasher::new(algorithm)?;
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        match reader.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => hasher.update(&buf[..n]),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(hasher.finalize_hex())
+}
+
+pub fn hash_file<P: AsRef<Path>>(path: P, algorithm: &str) -> io::Result<String> {
+    hash_reader(File::open(path)?, algorithm)
+}
+
+pub fn hash_bytes(data: &[u8], algorithm: &str) -> io::Result<String> {
+    let mut hasher = Hasher::new(algorithm)?;
+    hasher.update(data);
+    Ok(hasher.finalize_hex())
+}
+
+/// Parses a line of a checksum manifest as written by `sha256sum` and similar tools: `<hash>  <name>`, or
+/// `<hash> *<name>` for files hashed in binary mode.
+pub fn parse_checksum_line(line: &str) -> Option<(&str, &str)> {
+    let (hash, name) = line.split_once(' ')?;
+    let name = name.strip_prefix(' ').or_else(|| name.strip_prefix('*'))?;
+    if hash.is_empty() || name.is_empty() || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    Some((hash, name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash_bytes() {
+        let data = b"lush";
+        assert_eq!(hash_bytes(data, "md5").unwrap(), hash_bytes(data, "MD5").unwrap());
+        assert_eq!(hash_bytes(b"", "sha256").unwrap(), "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+        assert_eq!(&hash_bytes(b"", "sha512").unwrap()[..32], "cf83e1357eefb8bdf1542850d66d8007");
+        assert_eq!(hash_bytes(b"", "sha1").unwrap(), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+        assert_eq!(hash_bytes(b"", "md5").unwrap(), "d41d8cd98f00b204e9800998ecf8427e");
+        assert_eq!(hash_bytes(b"", "blake3").unwrap(), "af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262");
+        assert!(hash_bytes(data, "crc32").is_err());
+    }
+
+    #[test]
+    fn test_hash_reader() {
+        let data = vec![7u8; 200 * 1024];
+        assert_eq!(hash_reader(&data[..], "sha256").unwrap(), hash_bytes(&data, "sha256").unwrap());
+    }
+
+    #[test]
+    fn test_parse_checksum_line() {
+        assert_eq!(parse_checksum_line("abc123  file.txt"), Some(("abc123", "file.txt")));
+        assert_eq!(parse_checksum_line("abc123 *dir/file name.bin"), Some(("abc123", "dir/file name.bin")));
+        assert_eq!(parse_checksum_line("not a checksum"), None);
+        assert_eq!(parse_checksum_line(""), None);
+    }
+}
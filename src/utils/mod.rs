@@ -1 +1,5 @@
 pub mod dyn_format;
+pub mod atomic_write;
+pub mod duration;
+pub mod hash;
+pub mod progress;
@@ -0,0 +1,22 @@
+use std::io::IsTerminal;
+use indicatif::{ProgressBar, ProgressStyle};
+
+/// Returns a progress bar counting bytes, drawn on stderr.
+///
+/// The bar is hidden when stderr is not a terminal, so the output of scripts redirected to a file is not
+/// polluted. Without `total`, a spinner shows the bytes processed so far.
+pub fn bytes_progress(total: Option<u64>, message: &str) -> ProgressBar {
+    if !std::io::stderr().is_terminal() {
+        return ProgressBar::hidden();
+    }
+
+    let (pb, template) = match total {
+        Some(total) => (ProgressBar::new(total),
+                        "{msg} [{bar:40}] {bytes}/{total_bytes} ({bytes_per_sec}, {eta})"),
+        None => (ProgressBar::no_length(), "{spinner} {msg} {bytes} ({bytes_per_sec})"),
+    };
+    // The templates are constant, so they are always valid
+    pb.set_style(ProgressStyle::with_template(template).unwrap().progress_chars("=> "));
+    pb.set_message(message.to_string());
+    pb
+}